@@ -87,7 +87,7 @@ fn new_impl(b: &mut Bencher) {
   use glob_prime::glob::glob;
 
   fn glob_vec(pattern: &str) -> Vec<Path> {
-    glob(pattern).unwrap().collect()
+    glob(pattern).unwrap().map(|e| e.into_path()).collect()
   }
 
   let root = TempDir::new("glob-tests");
@@ -134,3 +134,23 @@ fn new_impl(b: &mut Bencher) {
   b.iter(|| glob_vec("**/*"));
 }
 
+
+#[bench]
+fn wide_directory(b: &mut Bencher) {
+  use glob_prime::glob::glob;
+
+  let root = TempDir::new("glob-tests");
+  let root = root.ok().expect("Should have created a temp directory");
+  assert!(os::change_dir(root.path()).is_ok());
+
+  mk_file("wide", true);
+
+  for i in range(0u, 100000) {
+    mk_file(format!("wide/{}.o", i).as_slice(), false);
+  }
+
+  mk_file("wide/needle.txt", false);
+
+  // the listing is streamed, so memory stays flat however wide `wide` is
+  b.iter(|| glob("wide/*.txt").unwrap().count());
+}
@@ -0,0 +1,154 @@
+//! The index behind `TarArchive` and `ZipArchive`.
+//!
+//! Members are added to a `MemoryFs` tree as they're read, with the
+//! directories their names imply filled in, so archives are matched with
+//! the same patterns as on disk. Names are kept as bytes, and absolute ones
+//! are taken from the root.
+
+use std::collections::HashMap;
+use std::io::{IoError, IoResult};
+use std::io;
+
+use entry::{FileKind, GlobEntry, Metadata};
+use filesystem::{FileSystem, DirEntries};
+use glob::{Paths, glob_in};
+use memfs::MemoryFs;
+use options::GlobOptions;
+use pattern::Error;
+
+/// What the index needs of an archive's members.
+pub trait Member: Clone {
+  /// Where the member is in the archive, to list them in order.
+  fn offset(&self) -> u64;
+
+  /// The member's path, set to its normalized name when it's added.
+  fn path_mut(&mut self) -> &mut Path;
+
+  /// The target of a symlink.
+  fn link(&self) -> Option<&Path> {
+    None
+  }
+}
+
+/// A path matched in an archive, along with its member; directories the
+/// archive only implies have none.
+pub struct ArchiveMatch<M> {
+  pub entry: GlobEntry,
+  pub member: Option<M>,
+}
+
+pub struct Archive<M> {
+  tree: MemoryFs,
+  members: HashMap<Path, M>,
+}
+
+impl<M: Member> Archive<M> {
+  pub fn new() -> Archive<M> {
+    Archive {
+      tree: MemoryFs::new(),
+      members: HashMap::new(),
+    }
+  }
+
+  /// Adds the member named `name`, of the kind `metadata` gives. A member
+  /// placed under one that isn't a directory is an error.
+  pub fn add(&mut self, name: &[u8], mut member: M, metadata: Metadata) -> IoResult<()> {
+    let start = name.iter().position(|&b| b != b'/').unwrap_or(name.len());
+    let name = name.slice_from(start);
+    let path = Path::new(name);
+    let components: Vec<&[u8]> = path.components().collect();
+
+    // names like `.` and `a/..` name the root, which is already there
+    if components.is_empty() {
+      return Ok(());
+    }
+    let mut above = Path::new(".");
+
+    for component in components.slice_to(components.len() - 1).iter() {
+      above.push(*component);
+
+      match self.tree.file_kind(&above) {
+        Ok(FileKind::Directory) | Err(..) => {}
+        Ok(..) => {
+          return Err(IoError {
+            kind: io::InvalidInput,
+            desc: "archive member under one that isn't a directory",
+            detail: Some(path.display().to_string()),
+          });
+        }
+      }
+    }
+
+    match (metadata.kind, member.link()) {
+      (FileKind::Symlink, Some(target)) => { self.tree.add_symlink(name, target.as_vec()); }
+      (FileKind::Directory, _) => { self.tree.add_dir(name); }
+      _ => { self.tree.add_file(name); }
+    }
+
+    self.tree.set_metadata(name, |m| {
+      *m = Metadata { kind: m.kind, ..metadata };
+    });
+
+    *member.path_mut() = path.clone();
+    self.members.insert(path, member);
+    Ok(())
+  }
+
+  /// The member at `path`, relative to the root of the archive.
+  pub fn member(&self, path: &Path) -> Option<&M> {
+    match path.path_relative_from(&Path::new("/")) {
+      Some(ref relative) if path.is_absolute() => self.members.get(relative),
+      _ => self.members.get(path),
+    }
+  }
+
+  /// Every member, in the order they're stored.
+  pub fn members(&self) -> Vec<&M> {
+    let mut members: Vec<&M> = self.members.values().collect();
+    members.sort_by(|a, b| a.offset().cmp(&b.offset()));
+    members
+  }
+
+  /// Globs the archive's members; relative patterns start from its root.
+  pub fn glob<'a>(&'a self, pattern: &str, options: &GlobOptions)
+                  -> Result<ArchiveMatches<'a, M>, Error> {
+    Ok(ArchiveMatches {
+      archive: self,
+      paths: try!(glob_in(&self.tree, pattern, options)),
+    })
+  }
+}
+
+impl<M: Member> FileSystem for Archive<M> {
+  fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+    self.tree.read_dir(path)
+  }
+
+  fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+    self.tree.file_kind(path)
+  }
+
+  fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+    self.tree.metadata(path)
+  }
+
+  fn read_link(&self, path: &Path) -> IoResult<Path> {
+    self.tree.read_link(path)
+  }
+}
+
+pub struct ArchiveMatches<'a, M: 'a> {
+  archive: &'a Archive<M>,
+  paths: Paths<&'a MemoryFs>,
+}
+
+impl<'a, M: Member> Iterator for ArchiveMatches<'a, M> {
+  type Item = ArchiveMatch<M>;
+
+  fn next(&mut self) -> Option<ArchiveMatch<M>> {
+    self.paths.next().map(|entry| {
+      let member = self.archive.member(entry.path()).map(|m| m.clone());
+      ArchiveMatch { entry: entry, member: member }
+    })
+  }
+}
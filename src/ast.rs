@@ -0,0 +1,493 @@
+//! Patterns as syntax trees, for inspecting them and building them.
+//!
+//! A pattern is an optional root followed by its components, each either
+//! `**` or a run of parts: literal text, `?`, `*`, character classes, and
+//! those last three named as in `{name:*}`.
+//! Trees print as canonical patterns, and parsing what a tree prints gives
+//! the same tree back.
+//!
+//! ```ignore
+//! let ast = Builder::new().literal("src").recursive().any_sequence().literal(".rs").build();
+//!
+//! assert_eq!(ast.to_string().as_slice(), "src/**/*.rs");
+//! assert_eq!(Ast::parse("src/**/*.rs", PathStyle::Posix).unwrap(), ast);
+//! ```
+//!
+//! Trees are only made by parsing and by `Builder`, which keep them in a
+//! form the syntax can express: consecutive `**` are collapsed, literal
+//! text is merged, and class members are ordered so they read back the
+//! same. More kinds of part may be added.
+
+use std::fmt;
+use std::mem;
+
+use pattern::{Pattern, PathStyle, Diagnostics, Error, Token, CharSpecifier};
+
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Ast {
+  root: Option<String>,
+  components: Vec<Component>,
+}
+
+#[derive(Clone, PartialEq, Eq, Show)]
+pub enum Component {
+  /// `**`, matching any number of components
+  Recursive,
+  /// the parts of a component; an empty one comes from a leading, trailing
+  /// or doubled separator
+  Parts(Vec<Part>),
+}
+
+#[derive(Clone, PartialEq, Eq, Show)]
+pub enum Part {
+  Literal(String),
+  /// `?`
+  AnyChar,
+  /// `*`
+  AnySequence,
+  /// `[...]` or `[!...]`
+  Class(Class),
+  /// `{name:...}`, naming a `?`, `*` or class
+  Named(String, Box<Part>),
+}
+
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Class {
+  negated: bool,
+  members: Vec<ClassMember>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum ClassMember {
+  Char(char),
+  Range(char, char),
+}
+
+// characters that are written as a class of their own in literals
+const SPECIAL: &'static [char] = &['*', '?', '[', '{'];
+
+impl Ast {
+  /// Parses `pattern`, failing with its first error.
+  pub fn parse(pattern: &str, style: PathStyle) -> Result<Ast, Error> {
+    let Diagnostics { tokens, spans, names, errors } = Pattern::diagnose(pattern, style);
+
+    if let Some(err) = errors.into_iter().next() {
+      return Err(err);
+    }
+
+    let chars: Vec<char> = pattern.chars().collect();
+    let (root, _) = style.split_root(pattern);
+    // the root's characters come first, and are taken as a whole
+    let skip = root.as_ref().map_or(0, |root| root.chars().count());
+
+    let mut builder = Builder::new();
+    builder.root = root;
+
+    for (i, (token, span)) in tokens.into_iter().zip(spans.into_iter()).enumerate().skip(skip) {
+      if let Some(&(_, ref name)) = names.iter().find(|&&(j, _)| j == i) {
+        let part = match token {
+          Token::AnyChar => Part::AnyChar,
+          Token::AnySequence => Part::AnySequence,
+          Token::AnyWithin(specs) => Part::Class(Class::new(false, members(specs).as_slice())),
+          Token::AnyExcept(specs) => Part::Class(Class::new(true, members(specs).as_slice())),
+          token => panic!("{:?} can't be named", token),
+        };
+
+        builder = builder.named(name.as_slice(), part);
+        continue;
+      }
+
+      builder = match token {
+        Token::Char('/') => builder.sep(),
+        Token::Char(c) => builder.push(Part::Literal(c.to_string())),
+        Token::AnyChar => builder.any_char(),
+        Token::AnySequence => builder.any_sequence(),
+        Token::AnyRecursiveSequence => {
+          // along with the separator after it, if there is one
+          let builder = builder.recursive();
+          if style.is_sep(chars[span.end - 1]) { builder.sep() } else { builder }
+        }
+        Token::AnyWithin(specs) => builder.class(members(specs).as_slice()),
+        Token::AnyExcept(specs) => builder.negated_class(members(specs).as_slice()),
+      };
+    }
+
+    Ok(builder.build())
+  }
+
+  /// The root, in its canonical form: `/` for Posix, or a drive or share
+  /// for Windows.
+  pub fn root(&self) -> Option<&str> {
+    self.root.as_ref().map(|root| root.as_slice())
+  }
+
+  pub fn components(&self) -> &[Component] {
+    self.components.as_slice()
+  }
+
+  /// Whether the pattern matches only itself.
+  pub fn is_literal(&self) -> bool {
+    self.components.iter().all(|component| match *component {
+      Component::Recursive => false,
+      Component::Parts(ref parts) => parts.iter().all(|part| match *part {
+        Part::Literal(..) => true,
+        _ => false,
+      }),
+    })
+  }
+
+  pub fn to_pattern(&self, style: PathStyle) -> Result<Pattern, Error> {
+    Pattern::with_style(self.to_string().as_slice(), style)
+  }
+}
+
+fn members(specs: Vec<CharSpecifier>) -> Vec<ClassMember> {
+  specs.into_iter().map(|spec| match spec {
+    CharSpecifier::SingleChar(c) => ClassMember::Char(c),
+    CharSpecifier::CharRange(a, b) => ClassMember::Range(a, b),
+  }).collect()
+}
+
+impl Class {
+  // orders the members so they read back the same: `]` has to come first,
+  // `-` is safest last, and a leading `!` would negate the class
+  fn new(negated: bool, members: &[ClassMember]) -> Class {
+    assert!(!members.is_empty(), "character classes can't be empty");
+
+    let is = |&: m: &ClassMember, c: char| *m == ClassMember::Char(c);
+    let mut ordered: Vec<ClassMember> = members.iter().filter(|m| is(*m, ']')).map(|m| *m).collect();
+    ordered.extend(members.iter().filter(|m| !is(*m, ']') && !is(*m, '-')).map(|m| *m));
+    ordered.extend(members.iter().filter(|m| is(*m, '-')).map(|m| *m));
+
+    if !negated && ordered.len() > 1 && is(&ordered[0], '!') {
+      ordered.swap(0, 1);
+    }
+
+    Class {
+      negated: negated,
+      members: ordered,
+    }
+  }
+
+  pub fn is_negated(&self) -> bool {
+    self.negated
+  }
+
+  pub fn members(&self) -> &[ClassMember] {
+    self.members.as_slice()
+  }
+}
+
+/// Builds a tree a part at a time.
+///
+/// Parts go into the current component until `sep` starts a new one;
+/// `recursive` adds a `**` component of its own.
+pub struct Builder {
+  root: Option<String>,
+  components: Vec<Component>,
+  current: Vec<Part>,
+  // the current component has been started, if only by a separator
+  open: bool,
+}
+
+impl Builder {
+  pub fn new() -> Builder {
+    Builder {
+      root: None,
+      components: Vec::new(),
+      current: Vec::new(),
+      open: true,
+    }
+  }
+
+  /// Sets the root, which should be in the canonical form for the style
+  /// the tree will be used with.
+  pub fn root(mut self, root: &str) -> Builder {
+    self.root = Some(root.to_string());
+    self
+  }
+
+  /// Adds literal text; a `/` in it separates components.
+  pub fn literal(mut self, text: &str) -> Builder {
+    for (i, piece) in text.split('/').enumerate() {
+      if i > 0 {
+        self = self.sep();
+      }
+
+      if !piece.is_empty() {
+        self = self.push(Part::Literal(piece.to_string()));
+      }
+    }
+
+    self
+  }
+
+  pub fn any_char(self) -> Builder {
+    self.push(Part::AnyChar)
+  }
+
+  pub fn any_sequence(self) -> Builder {
+    self.push(Part::AnySequence)
+  }
+
+  /// Adds a class matching any of `members`, which can't be empty.
+  pub fn class(self, members: &[ClassMember]) -> Builder {
+    // how the special characters are written literally
+    if members.len() == 1 {
+      if let ClassMember::Char(c) = members[0] {
+        if c == '!' || SPECIAL.contains(&c) {
+          return self.push(Part::Literal(c.to_string()));
+        }
+      }
+    }
+
+    self.push(Part::Class(Class::new(false, members)))
+  }
+
+  /// Adds a class matching anything but `members`, which can't be empty.
+  pub fn negated_class(self, members: &[ClassMember]) -> Builder {
+    self.push(Part::Class(Class::new(true, members)))
+  }
+
+  /// Adds `part`, which should be a `?`, `*` or class, named `name`.
+  pub fn named(self, name: &str, part: Part) -> Builder {
+    match part {
+      Part::AnyChar | Part::AnySequence | Part::Class(..) => {}
+      _ => panic!("only `?`, `*` and classes can be named"),
+    }
+
+    self.push(Part::Named(name.to_string(), Box::new(part)))
+  }
+
+  /// Ends the current component. At the very start of a builder without
+  /// a root, it makes the pattern absolute instead, setting the root to
+  /// `/`.
+  pub fn sep(mut self) -> Builder {
+    if self.root.is_none() && self.components.is_empty() && self.current.is_empty() {
+      self.root = Some("/".to_string());
+      return self;
+    }
+
+    if self.open {
+      let parts = mem::replace(&mut self.current, Vec::new());
+      self.components.push(Component::Parts(parts));
+    }
+
+    self.open = true;
+    self
+  }
+
+  /// Adds `**` as a component of its own, ending the current one first if
+  /// it has anything in it.
+  pub fn recursive(mut self) -> Builder {
+    if !self.current.is_empty() {
+      self = self.sep();
+    }
+
+    // `**/**` is no different from `**`
+    if self.components.last() != Some(&Component::Recursive) {
+      self.components.push(Component::Recursive);
+    }
+
+    self.open = false;
+    self
+  }
+
+  pub fn build(mut self) -> Ast {
+    if self.open {
+      self.components.push(Component::Parts(self.current));
+    }
+
+    Ast {
+      root: self.root,
+      components: self.components,
+    }
+  }
+
+  fn push(mut self, part: Part) -> Builder {
+    self.open = true;
+
+    let merged = match (self.current.last_mut(), &part) {
+      (Some(&mut Part::Literal(ref mut text)), &Part::Literal(ref more)) => {
+        text.push_str(more.as_slice());
+        true
+      }
+      _ => false,
+    };
+
+    if !merged {
+      self.current.push(part);
+    }
+
+    self
+  }
+}
+
+impl fmt::String for Ast {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if let Some(ref root) = self.root {
+      try!(write!(f, "{}", root));
+    }
+
+    for (i, component) in self.components.iter().enumerate() {
+      if i > 0 {
+        try!(write!(f, "/"));
+      }
+
+      try!(write!(f, "{}", component));
+    }
+
+    Ok(())
+  }
+}
+
+impl fmt::String for Component {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Component::Recursive => write!(f, "**"),
+      Component::Parts(ref parts) => {
+        for part in parts.iter() {
+          try!(write!(f, "{}", part));
+        }
+
+        Ok(())
+      }
+    }
+  }
+}
+
+impl fmt::String for Part {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Part::Literal(ref text) => {
+        for c in text.chars() {
+          if SPECIAL.contains(&c) {
+            try!(write!(f, "[{}]", c));
+          } else {
+            try!(write!(f, "{}", c));
+          }
+        }
+
+        Ok(())
+      }
+      Part::AnyChar => write!(f, "?"),
+      Part::AnySequence => write!(f, "*"),
+      Part::Class(ref class) => write!(f, "{}", class),
+      Part::Named(ref name, ref part) => write!(f, "{{{}:{}}}", name, part),
+    }
+  }
+}
+
+impl fmt::String for Class {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    try!(write!(f, "{}", if self.negated { "[!" } else { "[" }));
+
+    for member in self.members.iter() {
+      try!(match *member {
+        ClassMember::Char(c) => write!(f, "{}", c),
+        ClassMember::Range(a, b) => write!(f, "{}-{}", a, b),
+      });
+    }
+
+    write!(f, "]")
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Ast, Builder, Component, Part, ClassMember};
+  use pattern::PathStyle;
+
+  fn round_trip(pattern: &str, style: PathStyle) -> String {
+    let ast = Ast::parse(pattern, style).unwrap();
+    let printed = ast.to_string();
+    assert_eq!(Ast::parse(printed.as_slice(), style).unwrap(), ast);
+    printed
+  }
+
+  #[test]
+  fn parse() {
+    let ast = Ast::parse("/src/**/[!a-c]?*.rs", PathStyle::Posix).unwrap();
+
+    assert_eq!(ast.root(), Some("/"));
+    assert_eq!(ast.components()[0], Component::Parts(vec![Part::Literal("src".to_string())]));
+    assert_eq!(ast.components()[1], Component::Recursive);
+
+    match ast.components()[2] {
+      Component::Parts(ref parts) => {
+        match parts[0] {
+          Part::Class(ref class) => {
+            assert!(class.is_negated());
+            assert_eq!(class.members(), [ClassMember::Range('a', 'c')].as_slice());
+          }
+          ref part => panic!("{:?}", part),
+        }
+        assert_eq!(parts.slice_from(1), [
+          Part::AnyChar,
+          Part::AnySequence,
+          Part::Literal(".rs".to_string()),
+        ].as_slice());
+      }
+      Component::Recursive => panic!(),
+    }
+
+    assert!(Ast::parse("a/**b", PathStyle::Posix).is_err());
+  }
+
+  #[test]
+  fn printing() {
+    for pattern in ["", "a", "/", "a/", "//a", "**", "**/", "a/**", "a/**/b", "[*]x[?]",
+                    "[]a-]", "[!]!]", "[b!]", "é/*.[ch]"].iter() {
+      assert_eq!(round_trip(*pattern, PathStyle::Posix).as_slice(), *pattern);
+    }
+
+    // canonical forms
+    assert_eq!(round_trip("a/**/**/b", PathStyle::Posix).as_slice(), "a/**/b");
+    assert_eq!(round_trip("**/**", PathStyle::Posix).as_slice(), "**");
+    assert_eq!(round_trip(r"\\?\c:\Users\**\*.txt", PathStyle::Windows).as_slice(),
+               "C:/Users/**/*.txt");
+    assert_eq!(round_trip(r"\\server\share", PathStyle::Windows).as_slice(), "//server/share");
+  }
+
+  #[test]
+  fn builder() {
+    let ast = Builder::new().literal("src").recursive().any_sequence().literal(".rs").build();
+    assert_eq!(ast.to_string().as_slice(), "src/**/*.rs");
+    assert_eq!(Ast::parse("src/**/*.rs", PathStyle::Posix).unwrap(), ast);
+
+    let ast = Builder::new()
+      .literal("a*/b")
+      .class(&[ClassMember::Char('-'), ClassMember::Range('0', '9'), ClassMember::Char(']')])
+      .sep()
+      .class(&[ClassMember::Char('!'), ClassMember::Char('x')])
+      .class(&[ClassMember::Char('?')])
+      .build();
+    assert_eq!(ast.to_string().as_slice(), "a[*]/b[]0-9-]/[x!][?]");
+    assert_eq!(Ast::parse(ast.to_string().as_slice(), PathStyle::Posix).unwrap(), ast);
+    assert!(!ast.is_literal());
+
+    let ast = Builder::new().root("C:/").literal("Windows").recursive().recursive().sep().build();
+    assert_eq!(ast.to_string().as_slice(), "C:/Windows/**/");
+    assert_eq!(Ast::parse(r"c:\Windows\**\", PathStyle::Windows).unwrap(), ast);
+    assert!(Builder::new().literal("a/b?").build().is_literal());
+
+    // a leading separator is the root
+    let ast = Builder::new().literal("/usr").build();
+    assert_eq!(ast.to_string().as_slice(), "/usr");
+    assert_eq!(Ast::parse("/usr", PathStyle::Posix).unwrap(), ast);
+    assert_eq!(Builder::new().sep().literal("usr").build(), ast);
+
+    let ast = Builder::new()
+      .literal("crates/{x}")
+      .named("crate", Part::AnySequence)
+      .sep()
+      .named("kind", Part::Class(match Ast::parse("[!a-c]", PathStyle::Posix).unwrap().components()[0] {
+        Component::Parts(ref parts) => match parts[0] { Part::Class(ref class) => class.clone(), _ => panic!() },
+        _ => panic!(),
+      }))
+      .build();
+    assert_eq!(ast.to_string().as_slice(), "crates/[{]x}{crate:*}/{kind:[!a-c]}");
+    assert_eq!(Ast::parse(ast.to_string().as_slice(), PathStyle::Posix).unwrap(), ast);
+    assert!(!ast.is_literal());
+  }
+}
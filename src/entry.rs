@@ -0,0 +1,213 @@
+//! Entries yielded by the glob walker.
+
+use std::cell::{Cell, RefCell};
+use std::io::{FileStat, FileType, IoResult};
+
+use filesystem::FileSystem;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Show)]
+pub enum FileKind {
+  File,
+  Directory,
+  Symlink,
+  Fifo,
+  Socket,
+  BlockDevice,
+  CharDevice,
+  Other,
+}
+
+impl FileKind {
+  pub fn from_file_type(kind: FileType) -> FileKind {
+    match kind {
+      FileType::RegularFile => FileKind::File,
+      FileType::Directory => FileKind::Directory,
+      FileType::Symlink => FileKind::Symlink,
+      FileType::NamedPipe => FileKind::Fifo,
+      FileType::BlockSpecial => FileKind::BlockDevice,
+      // std doesn't distinguish sockets and character devices
+      FileType::Unknown => FileKind::Other,
+    }
+  }
+}
+
+/// The subset of `stat` information the walker cares about.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub struct Metadata {
+  pub kind: FileKind,
+  pub size: u64,
+  /// milliseconds since the epoch
+  pub modified: u64,
+  pub permissions: u32,
+  pub uid: u64,
+  pub gid: u64,
+}
+
+impl Metadata {
+  pub fn from_stat(stat: &FileStat) -> Metadata {
+    Metadata {
+      kind: FileKind::from_file_type(stat.kind),
+      size: stat.size,
+      modified: stat.modified,
+      permissions: stat.perm.bits(),
+      uid: stat.unstable.uid,
+      gid: stat.unstable.gid,
+    }
+  }
+}
+
+/// A path found by the walker.
+///
+/// The file type is recorded when it's already known, e.g. from `d_type`,
+/// and metadata is fetched at most once, so asking the same entry several
+/// questions doesn't cost several `stat` calls.
+///
+/// Lookups take the `FileSystem` the entry was found on, e.g. `OsFs` for
+/// `glob`, since an entry from an archive or an in-memory tree has nothing
+/// to do with the host's files. Anything the walker already looked up is
+/// cached.
+#[derive(Clone)]
+pub struct GlobEntry {
+  path: Path,
+  depth: usize,
+
+  // type of the entry itself, without following symlinks
+  kind: Cell<Option<FileKind>>,
+  // the followed metadata, i.e. of the symlink target
+  metadata: RefCell<Option<Metadata>>,
+}
+
+impl GlobEntry {
+  pub fn new(path: Path, depth: usize) -> GlobEntry {
+    GlobEntry {
+      path: path,
+      depth: depth,
+      kind: Cell::new(None),
+      metadata: RefCell::new(None),
+    }
+  }
+
+  pub fn with_kind(path: Path, depth: usize, kind: Option<FileKind>) -> GlobEntry {
+    let entry = GlobEntry::new(path, depth);
+    entry.kind.set(kind);
+    entry
+  }
+
+  pub fn path<'a>(&'a self) -> &'a Path {
+    &self.path
+  }
+
+  pub fn into_path(self) -> Path {
+    self.path
+  }
+
+  /// The number of path components below the glob's starting directory.
+  pub fn depth(&self) -> usize {
+    self.depth
+  }
+
+  /// The type of the entry itself; a symlink is reported as `Symlink`.
+  ///
+  /// Returns `None` if the entry no longer exists.
+  pub fn file_kind_in<F: FileSystem>(&self, fs: &F) -> Option<FileKind> {
+    if let Some(kind) = self.kind.get() {
+      return Some(kind);
+    }
+
+    let kind = fs.file_kind(&self.path).ok();
+
+    self.kind.set(kind);
+    kind
+  }
+
+  /// Whether the entry is a directory, following symlinks.
+  pub fn is_dir_in<F: FileSystem>(&self, fs: &F) -> bool {
+    match self.kind.get() {
+      Some(FileKind::Symlink) | None => (),
+      Some(kind) => return kind == FileKind::Directory,
+    }
+
+    self.metadata_in(fs).map(|m| m.kind == FileKind::Directory).unwrap_or(false)
+  }
+
+  /// Whether the entry exists, following symlinks.
+  pub fn exists_in<F: FileSystem>(&self, fs: &F) -> bool {
+    match self.kind.get() {
+      Some(FileKind::Symlink) | None => self.metadata_in(fs).is_ok(),
+      Some(..) => true,
+    }
+  }
+
+  /// The entry's metadata, following symlinks. Cached after the first call.
+  pub fn metadata_in<F: FileSystem>(&self, fs: &F) -> IoResult<Metadata> {
+    if let Some(metadata) = *self.metadata.borrow() {
+      return Ok(metadata);
+    }
+
+    let metadata = try!(fs.metadata(&self.path));
+    *self.metadata.borrow_mut() = Some(metadata);
+
+    Ok(metadata)
+  }
+
+  /// Where the entry points, if it's a symlink.
+  pub fn read_link_in<F: FileSystem>(&self, fs: &F) -> IoResult<Path> {
+    fs.read_link(&self.path)
+  }
+}
+
+impl PartialEq for GlobEntry {
+  fn eq(&self, other: &GlobEntry) -> bool {
+    self.path == other.path
+  }
+}
+
+impl Eq for GlobEntry {}
+
+#[cfg(test)]
+mod test {
+  use std::io::{self, TempDir};
+  use std::io::fs;
+
+  use super::{GlobEntry, FileKind};
+  use filesystem::OsFs;
+
+  #[test]
+  fn kinds() {
+    let root = TempDir::new("glob-entry").unwrap();
+    let dir = root.path().join("dir");
+    let file = root.path().join("file");
+    let link = root.path().join("link");
+
+    fs::mkdir(&dir, io::USER_RWX).unwrap();
+    io::File::create(&file).unwrap();
+    fs::symlink(&dir, &link).unwrap();
+
+    let entry = GlobEntry::new(dir, 1);
+    assert_eq!(entry.file_kind_in(&OsFs), Some(FileKind::Directory));
+    assert!(entry.is_dir_in(&OsFs));
+
+    let entry = GlobEntry::new(file, 1);
+    assert_eq!(entry.file_kind_in(&OsFs), Some(FileKind::File));
+    assert!(!entry.is_dir_in(&OsFs));
+    assert!(entry.exists_in(&OsFs));
+
+    // a symlink to a directory is a directory as far as walking goes
+    let entry = GlobEntry::new(link, 1);
+    assert_eq!(entry.file_kind_in(&OsFs), Some(FileKind::Symlink));
+    assert!(entry.is_dir_in(&OsFs));
+
+    let entry = GlobEntry::new(root.path().join("missing"), 1);
+    assert_eq!(entry.file_kind_in(&OsFs), None);
+    assert!(!entry.exists_in(&OsFs));
+  }
+
+  #[test]
+  fn known_kind_skips_stat() {
+    // the path doesn't exist, so any answer must come from the known kind
+    let entry =
+      GlobEntry::with_kind(Path::new("/does/not/exist"), 0, Some(FileKind::Directory));
+    assert!(entry.is_dir_in(&OsFs));
+    assert!(entry.exists_in(&OsFs));
+  }
+}
@@ -0,0 +1,372 @@
+//! Translating patterns into the syntaxes of other matchers.
+//!
+//! Exports keep the pattern's meaning or fail: each target gets a pattern
+//! matching the same paths as `Pattern::matches_bytes` does, taking them in
+//! the style's canonical form (`/` separators, Windows roots spelled as in
+//! `PathStyle::normalize`). Like the pattern, exports match at the end of a
+//! string but may start anywhere in it.
+//!
+//! `?` and `**` match newlines there, and so in the exports, though not in
+//! `Pattern::matches`, whose regex leaves them out; only for strings
+//! without newlines do all three agree.
+//!
+//! `*` can't be exported to `LIKE` or SQLite `GLOB`, since their only
+//! sequence wildcard crosses separators; that makes it `**`'s equivalent.
+
+use std::fmt;
+use std::ops::Range;
+
+use pattern::{Pattern, Diagnostics, Token, CharSpecifier};
+use pattern::CharSpecifier::{SingleChar, CharRange};
+
+/// A syntax to export patterns to.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum Syntax {
+  /// A regex for PCRE, or for JavaScript with the `u` flag.
+  Pcre,
+  /// A POSIX extended regex, matching whole strings as `find -regex` does,
+  /// though anchored for searching too.
+  PosixEre,
+  /// A SQL `LIKE` pattern, to be used with `ESCAPE` and the given
+  /// character. Matching is taken to be case-sensitive.
+  SqlLike(char),
+  /// A SQLite `GLOB` pattern.
+  SqliteGlob,
+}
+
+/// A part of a pattern the target syntax can't express.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct ExportError {
+  pub syntax: Syntax,
+  /// the part of the pattern, in characters
+  pub char_span: Range<usize>,
+  pub reason: &'static str,
+}
+
+impl fmt::String for ExportError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "can't export to {:?} at {}..{}: {}",
+           self.syntax, self.char_span.start, self.char_span.end, self.reason)
+  }
+}
+
+impl Pattern {
+  /// The pattern in another syntax, or the first part of it that can't be
+  /// expressed there.
+  pub fn export(&self, syntax: Syntax) -> Result<String, ExportError> {
+    let Diagnostics { tokens, spans, .. } = Pattern::diagnose(self.as_str(), self.style());
+    let fold = !self.style().is_case_sensitive();
+    let mut out = String::new();
+
+    // everything but the regex matches whole strings, unless the pattern
+    // starts with `**` anyway
+    let leading = tokens.first() == Some(&Token::AnyRecursiveSequence);
+
+    match syntax {
+      Syntax::Pcre => {}
+      Syntax::PosixEre if leading => out.push('^'),
+      _ if leading => {}
+      Syntax::PosixEre => out.push_str("^.*"),
+      Syntax::SqlLike(..) => out.push('%'),
+      Syntax::SqliteGlob => out.push('*'),
+    }
+
+    for (token, span) in tokens.iter().zip(spans.iter()) {
+      let exported = match syntax {
+        Syntax::Pcre => pcre(&mut out, token, fold),
+        Syntax::PosixEre => ere(&mut out, token, fold),
+        Syntax::SqlLike(escape) => like(&mut out, token, fold, escape),
+        Syntax::SqliteGlob => glob(&mut out, token, fold),
+      };
+
+      try!(exported.map_err(|reason| {
+        ExportError {
+          syntax: syntax,
+          char_span: span.clone(),
+          reason: reason,
+        }
+      }));
+    }
+
+    match syntax {
+      Syntax::Pcre => out.push_str(r"(?![\s\S])"),
+      Syntax::PosixEre => out.push('$'),
+      _ => {}
+    }
+
+    Ok(out)
+  }
+}
+
+fn has_case(c: char) -> bool {
+  c.to_lowercase() != c || c.to_uppercase() != c
+}
+
+// the class with the other case of each member added
+fn fold_class(specs: &[CharSpecifier]) -> Result<Vec<CharSpecifier>, &'static str> {
+  let mut folded = Vec::new();
+
+  for &spec in specs.iter() {
+    folded.push(spec);
+
+    match spec {
+      SingleChar(c) => {
+        for other in [c.to_lowercase(), c.to_uppercase()].iter() {
+          if *other != c {
+            folded.push(SingleChar(*other));
+          }
+        }
+      }
+      CharRange(a, b) if (b as u32) < 0x80 => {
+        // the letters in the range, in the other case
+        for &(lo, hi) in [('a', 'z'), ('A', 'Z')].iter() {
+          let (from, to) = (if a > lo { a } else { lo }, if b < hi { b } else { hi });
+
+          if from <= to {
+            let swap = |&: c: char| if lo == 'a' { c.to_uppercase() } else { c.to_lowercase() };
+            folded.push(CharRange(swap(from), swap(to)));
+          }
+        }
+      }
+      CharRange(..) => return Err("case-insensitive character ranges beyond ASCII"),
+    }
+  }
+
+  Ok(folded)
+}
+
+// the members of a bracket expression in the order POSIX and SQLite read
+// literally: `]` first, `^` anywhere else, `-` last
+fn bracket(specs: &[CharSpecifier], negated: bool) -> Result<String, &'static str> {
+  let mut singles: Vec<char> = Vec::new();
+  let mut ranges = String::new();
+
+  for &spec in specs.iter() {
+    match spec {
+      SingleChar(c) => if !singles.contains(&c) { singles.push(c) },
+      CharRange(a, b) => {
+        if ['[', ']', '-', '^'].iter().any(|&c| a == c || b == c) {
+          return Err("character ranges starting or ending in `[`, `]`, `-` or `^`");
+        }
+
+        ranges.push(a);
+        ranges.push('-');
+        ranges.push(b);
+      }
+    }
+  }
+
+  let is_special = |&: c: &char| ['[', ']', '-', '^'].contains(c);
+  let mut out = String::new();
+
+  if singles.contains(&']') {
+    out.push(']');
+  }
+
+  out.push_str(ranges.as_slice());
+  out.extend(singles.iter().filter(|c| !is_special(*c)).map(|c| *c));
+
+  // a `[` followed by `.`, `:` or `=` starts a POSIX class, so it goes
+  // after the others; `^` can't be first
+  for &c in ['[', '^', '-'].iter() {
+    if singles.contains(&c) {
+      if c == '^' && out.is_empty() && !negated {
+        if singles.contains(&'-') {
+          out.push('-');
+          out.push('^');
+          return Ok(out);
+        }
+
+        return Err("a class of only `^`");
+      }
+
+      out.push(c);
+    }
+  }
+
+  Ok(out)
+}
+
+// the character a class matches, when it only matches one
+fn lone(specs: &[CharSpecifier], negated: bool, fold: bool) -> Option<char> {
+  match specs.first() {
+    Some(&SingleChar(c)) if specs.len() == 1 && !negated && !(fold && has_case(c)) => Some(c),
+    _ => None,
+  }
+}
+
+fn pcre(out: &mut String, token: &Token, fold: bool) -> Result<(), &'static str> {
+  let escape = |&: out: &mut String, c: char, special: &str| {
+    if special.contains_char(c) {
+      out.push('\\');
+    }
+    out.push(c);
+  };
+
+  let class = |&: out: &mut String, specs: &[CharSpecifier], negated: bool|
+                -> Result<(), &'static str> {
+    let folded;
+    let specs = if fold { folded = try!(fold_class(specs)); folded.as_slice() } else { specs };
+
+    out.push_str(if negated { "[^" } else { "[" });
+
+    for &spec in specs.iter() {
+      match spec {
+        SingleChar(c) => escape(out, c, r"\]^[-"),
+        CharRange(a, b) => {
+          escape(out, a, r"\]^[-");
+          out.push('-');
+          escape(out, b, r"\]^[-");
+        }
+      }
+    }
+
+    out.push(']');
+    Ok(())
+  };
+
+  match *token {
+    Token::Char(c) if fold && has_case(c) => class(out, &[SingleChar(c)], false),
+    Token::Char(c) => Ok(escape(out, c, r"\^$.|?*+()[]{}/")),
+    Token::AnyChar => Ok(out.push_str(r"[\s\S]")),
+    Token::AnySequence => Ok(out.push_str("[^/]*")),
+    Token::AnyRecursiveSequence => Ok(out.push_str(r"[\s\S]*")),
+    Token::AnyWithin(ref specs) => class(out, specs.as_slice(), false),
+    Token::AnyExcept(ref specs) => class(out, specs.as_slice(), true),
+  }
+}
+
+fn ere(out: &mut String, token: &Token, fold: bool) -> Result<(), &'static str> {
+  let class = |&: out: &mut String, specs: &[CharSpecifier], negated: bool|
+                -> Result<(), &'static str> {
+    match lone(specs, negated, fold) {
+      Some(c) => return ere(out, &Token::Char(c), fold),
+      None => {}
+    }
+
+    let folded;
+    let specs = if fold { folded = try!(fold_class(specs)); folded.as_slice() } else { specs };
+
+    out.push_str(if negated { "[^" } else { "[" });
+    out.push_str(try!(bracket(specs, negated)).as_slice());
+    out.push(']');
+    Ok(())
+  };
+
+  match *token {
+    Token::Char(c) if fold && has_case(c) => class(out, &[SingleChar(c)], false),
+    Token::Char(c) => {
+      if r"\^$.|?*+()[{".contains_char(c) {
+        out.push('\\');
+      }
+      out.push(c);
+      Ok(())
+    }
+    Token::AnyChar => Ok(out.push('.')),
+    Token::AnySequence => Ok(out.push_str("[^/]*")),
+    Token::AnyRecursiveSequence => Ok(out.push_str(".*")),
+    Token::AnyWithin(ref specs) => class(out, specs.as_slice(), false),
+    Token::AnyExcept(ref specs) => class(out, specs.as_slice(), true),
+  }
+}
+
+fn like(out: &mut String, token: &Token, fold: bool, escape: char) -> Result<(), &'static str> {
+  match *token {
+    Token::Char(c) if fold && has_case(c) => Err("case-insensitive matching"),
+    Token::Char(c) => {
+      if c == '%' || c == '_' || c == escape {
+        out.push(escape);
+      }
+      out.push(c);
+      Ok(())
+    }
+    Token::AnyChar => Ok(out.push('_')),
+    Token::AnySequence => Err("`*`, which doesn't cross separators as `%` does"),
+    Token::AnyRecursiveSequence => Ok(out.push('%')),
+    Token::AnyWithin(..) | Token::AnyExcept(..) => Err("character classes"),
+  }
+}
+
+fn glob(out: &mut String, token: &Token, fold: bool) -> Result<(), &'static str> {
+  let class = |&: out: &mut String, specs: &[CharSpecifier], negated: bool|
+                -> Result<(), &'static str> {
+    match lone(specs, negated, fold) {
+      Some(c) if c == '^' => return Ok(out.push(c)),
+      _ => {}
+    }
+
+    let folded;
+    let specs = if fold { folded = try!(fold_class(specs)); folded.as_slice() } else { specs };
+
+    out.push_str(if negated { "[^" } else { "[" });
+    out.push_str(try!(bracket(specs, negated)).as_slice());
+    out.push(']');
+    Ok(())
+  };
+
+  match *token {
+    Token::Char(c) if fold && has_case(c) => class(out, &[SingleChar(c)], false),
+    Token::Char(c) if c == '*' || c == '?' || c == '[' => class(out, &[SingleChar(c)], false),
+    Token::Char(c) => Ok(out.push(c)),
+    Token::AnyChar => Ok(out.push('?')),
+    Token::AnySequence => Err("`*`, which doesn't cross separators as GLOB's `*` does"),
+    Token::AnyRecursiveSequence => Ok(out.push('*')),
+    Token::AnyWithin(ref specs) => class(out, specs.as_slice(), false),
+    Token::AnyExcept(ref specs) => class(out, specs.as_slice(), true),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Syntax;
+  use pattern::{Pattern, PathStyle};
+
+  fn export(pattern: &str, syntax: Syntax) -> String {
+    Pattern::with_style(pattern, PathStyle::Posix).unwrap().export(syntax).unwrap()
+  }
+
+  fn reason(pattern: &str, syntax: Syntax) -> (usize, &'static str) {
+    let err = Pattern::with_style(pattern, PathStyle::Posix).unwrap().export(syntax).unwrap_err();
+    (err.char_span.start, err.reason)
+  }
+
+  #[test]
+  fn pcre() {
+    assert_eq!(export("src/**/*.rs", Syntax::Pcre).as_slice(), r"src\/[\s\S]*[^/]*\.rs(?![\s\S])");
+    assert_eq!(export("[!]a-c^]?", Syntax::Pcre).as_slice(), r"[^\]a-c\^][\s\S](?![\s\S])");
+
+    let pattern = Pattern::with_style(r"C:\Temp\[a-f]*", PathStyle::Windows).unwrap();
+    assert_eq!(pattern.export(Syntax::Pcre).unwrap().as_slice(),
+               r"[Cc]:\/[Tt][eE][mM][pP]\/[a-fA-F][^/]*(?![\s\S])");
+    assert_eq!(export("**/x", Syntax::Pcre).as_slice(), r"[\s\S]*x(?![\s\S])");
+  }
+
+  #[test]
+  fn posix_ere() {
+    assert_eq!(export("a.b/**/*(1)", Syntax::PosixEre).as_slice(), r"^.*a\.b/.*[^/]*\(1\)$");
+    assert_eq!(export("**/x", Syntax::PosixEre).as_slice(), "^.*x$");
+    assert_eq!(export("[]^[-]", Syntax::PosixEre).as_slice(), "^.*[][^-]$");
+    assert_eq!(export("[!^-]", Syntax::PosixEre).as_slice(), "^.*[^^-]$");
+    assert_eq!(export("x[^][!^]", Syntax::PosixEre).as_slice(), r"^.*x\^[^^]$");
+    assert_eq!(reason("[]-a]", Syntax::PosixEre).0, 0);
+  }
+
+  #[test]
+  fn sql_like() {
+    assert_eq!(export("logs/**/50%_?.txt", Syntax::SqlLike('\\')).as_slice(),
+               r"%logs/%50\%\__.txt");
+    assert_eq!(export("a!b", Syntax::SqlLike('!')).as_slice(), "%a!!b");
+    assert_eq!(reason("logs/*.txt", Syntax::SqlLike('\\')).0, 5);
+    assert_eq!(reason("logs/[ab]", Syntax::SqlLike('\\')), (5, "character classes"));
+
+    let pattern = Pattern::with_style(r"C:\**", PathStyle::Windows).unwrap();
+    assert!(pattern.export(Syntax::SqlLike('\\')).is_err());
+  }
+
+  #[test]
+  fn sqlite_glob() {
+    assert_eq!(export("**/a?[*][!0-9]", Syntax::SqliteGlob).as_slice(), "*a?[*][^0-9]");
+    assert_eq!(export("x[^]", Syntax::SqliteGlob).as_slice(), "*x^");
+    assert_eq!(reason("src/*.rs", Syntax::SqliteGlob).0, 4);
+  }
+}
@@ -0,0 +1,127 @@
+//! The filesystem operations the walkers are built on.
+//!
+//! Everything the walkers know about a tree comes through `FileSystem`, so
+//! the same patterns can be matched against in-memory trees, archives or
+//! remote listings. `OsFs`, the operating system's filesystem, is what
+//! `glob` and `glob_with` use.
+
+use std::io::IoResult;
+use std::io::fs;
+
+use entry::{FileKind, Metadata};
+use readdir;
+
+/// A directory listing: each entry's full path, along with its file type
+/// when that's known without another lookup.
+pub type DirEntries = Box<Iterator<Item=(Path, Option<FileKind>)> + Send>;
+
+pub trait FileSystem {
+  /// Lists the entries of a directory, excluding `.` and `..`.
+  fn read_dir(&self, path: &Path) -> IoResult<DirEntries>;
+
+  /// The type of the entry itself, without following symlinks.
+  fn file_kind(&self, path: &Path) -> IoResult<FileKind>;
+
+  /// The entry's metadata, following symlinks.
+  fn metadata(&self, path: &Path) -> IoResult<Metadata>;
+
+  fn read_link(&self, path: &Path) -> IoResult<Path>;
+}
+
+impl<'a, F: FileSystem> FileSystem for &'a F {
+  fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+    (**self).read_dir(path)
+  }
+
+  fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+    (**self).file_kind(path)
+  }
+
+  fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+    (**self).metadata(path)
+  }
+
+  fn read_link(&self, path: &Path) -> IoResult<Path> {
+    (**self).read_link(path)
+  }
+}
+
+#[derive(Copy, Clone, Show)]
+pub struct OsFs;
+
+impl FileSystem for OsFs {
+  fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+    readdir::read_dir(path).map(|ents| Box::new(ents) as DirEntries)
+  }
+
+  fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+    fs::lstat(path).map(|st| FileKind::from_file_type(st.kind))
+  }
+
+  fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+    fs::stat(path).map(|st| Metadata::from_stat(&st))
+  }
+
+  fn read_link(&self, path: &Path) -> IoResult<Path> {
+    fs::readlink(path)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::cell::Cell;
+  use std::io::{self, IoResult, TempDir};
+  use std::io::fs;
+
+  use super::{FileSystem, DirEntries, OsFs};
+  use entry::{FileKind, Metadata};
+  use glob::glob_in;
+  use options::GlobOptions;
+
+  // forwards to the OS, counting the lookups made through it
+  struct Counting {
+    reads: Cell<usize>,
+    lookups: Cell<usize>,
+  }
+
+  impl FileSystem for Counting {
+    fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+      self.reads.set(self.reads.get() + 1);
+      OsFs.read_dir(path)
+    }
+
+    fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+      self.lookups.set(self.lookups.get() + 1);
+      OsFs.file_kind(path)
+    }
+
+    fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+      self.lookups.set(self.lookups.get() + 1);
+      OsFs.metadata(path)
+    }
+
+    fn read_link(&self, path: &Path) -> IoResult<Path> {
+      OsFs.read_link(path)
+    }
+  }
+
+  #[test]
+  fn walker_goes_through_the_trait() {
+    let root = TempDir::new("glob-filesystem").unwrap();
+    fs::mkdir(&root.path().join("a"), io::USER_RWX).unwrap();
+    fs::mkdir(&root.path().join("b"), io::USER_RWX).unwrap();
+    io::File::create(&root.path().join("a/x")).unwrap();
+
+    let counting = Counting { reads: Cell::new(0), lookups: Cell::new(0) };
+    let pattern = root.path().join("*/x");
+
+    let found: Vec<Path> =
+      glob_in(&counting, pattern.as_str().unwrap(), &GlobOptions::new()).unwrap()
+        .map(|e| e.into_path())
+        .collect();
+
+    assert_eq!(found, vec![root.path().join("a/x")]);
+    assert_eq!(counting.reads.get(), 1);
+    assert!(counting.lookups.get() > 0);
+  }
+}
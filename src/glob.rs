@@ -1,13 +1,11 @@
-use std::iter::Peekable;
-
-use std::io::fs::PathExtensions;
-use std::io::fs::readdir;
-
-use std::path::is_sep;
-
-use std::cmp::min;
-
-use pattern::{Pattern, Error};
+use std::vec;
+
+use pattern::{Pattern, Captures, Error};
+use entry::GlobEntry;
+use filesystem::{FileSystem, DirEntries, OsFs};
+use options::GlobOptions;
+use plan::{self, Plan, Component, Filter};
+use rewrite::Template;
 use self::Selector::{Terminating, Precise, Wildcard, Recursive};
 
 enum Selector {
@@ -20,82 +18,51 @@ enum Selector {
     successor: Box<Selector>,
 
     // book keeping
-    entries: Option<Vec<Path>>,
+    entries: Option<DirEntries>,
+    // the entry the successor is currently selecting from
+    current: Option<GlobEntry>,
   },
   Recursive {
     successor: Box<Selector>,
 
     // book keeping
-    directories: Option<Peekable<Path, Directories>>,
+    directories: Option<Directories>,
+    // the directory the successor is currently selecting from
+    current: Option<GlobEntry>,
   },
   Terminating {
     terminated: bool,
   },
 }
 
-static WILDCARD: ::regex::Regex = regex!(r"[[*?]");
-
 impl Selector {
-  // better name for this? perhaps surprising it returns a vec since name is
-  // Selector::from_pattern("blah")
-  fn from_pattern(pattern: &str) -> Result<Selector, Error> {
-    // compile pattern to make sure there are no immediate errors
-    let _compiled = try!(Pattern::new(pattern));
-    // TODO: should this be split on r"[^\]{SEP}"
-
-    let mut patterns: Vec<&str> = Vec::new();
-    let mut was_recursive = false;
-
-    // collapse consecutive recursive patterns
-    for pattern in pattern.split(is_sep) {
-      if pattern == "**" {
-        if was_recursive {
-          continue;
-        } else {
-          was_recursive = true;
-        }
-      } else {
-        was_recursive = false;
-      }
-
-      patterns.push(pattern);
-    }
-
-    return Selector::from_components(patterns.as_slice());
-  }
-
-  fn from_components(patterns: &[&str]) -> Result<Selector, Error> {
-    if !patterns.is_empty() {
-      let pattern = patterns[0];
-      let rest = patterns.slice_from(1);
-
-      if pattern == "**" {
-        return Ok(Recursive {
-          successor: Box::new(try!(Selector::from_components(rest))),
-          directories: None,
-        });
-      }
-
-      else if WILDCARD.is_match(pattern) {
-        let compiled = try!(Pattern::new(pattern));
-        return Ok(Wildcard {
-          pattern: compiled,
-          successor: Box::new(try!(Selector::from_components(rest))),
-          entries: None,
-        });
-      }
-
-      else {
-        return Ok(Precise {
-          pattern: pattern.to_string(),
-          successor: Box::new(try!(Selector::from_components(rest))),
-        });
+  fn from_components(components: Vec<Component>) -> Selector {
+    let terminating = Terminating { terminated: false };
+
+    components.into_iter().rev().fold(terminating, |successor, component| {
+      let successor = Box::new(successor);
+
+      match component {
+        Component::Recursive =>
+          Recursive {
+            successor: successor,
+            directories: None,
+            current: None,
+          },
+        Component::Wildcard(pattern) =>
+          Wildcard {
+            pattern: pattern,
+            successor: successor,
+            entries: None,
+            current: None,
+          },
+        Component::Precise(pattern) =>
+          Precise {
+            pattern: pattern,
+            successor: successor,
+          },
       }
-    } else {
-        return Ok(Terminating {
-          terminated: false,
-        });
-    }
+    })
   }
 
   fn is_terminating(&self) -> bool {
@@ -106,99 +73,126 @@ impl Selector {
     }
   }
 
-  fn select_from(&mut self, path: &Path, is_dir: bool) -> Option<Path> {
+  fn select_from<F: FileSystem>(&mut self, fs: &F, entry: &GlobEntry, filter: &Filter)
+                                -> Option<GlobEntry> {
     match *self {
       Precise {
         ref pattern,
         successor: ref mut successor
       } => {
-        let joined = path.join(pattern);
-
-        if path.is_dir() && joined.exists() {
-          return successor.select_from(&joined, is_dir);
-        } else {
+        if !entry.is_dir_in(fs) {
           return None;
         }
+
+        match plan::resolve(fs, entry, pattern.as_slice(), filter.options.style) {
+          Some(joined) => return successor.select_from(fs, &joined, filter),
+          None => return None,
+        }
       },
 
       Wildcard {
         ref pattern,
         successor: ref mut successor,
         ref mut entries,
+        ref mut current,
       } => {
-        if !path.is_dir() {
-          return None;
+        if entries.is_none() {
+          if !entry.is_dir_in(fs) {
+            return None;
+          }
+
+          match fs.read_dir(entry.path()) {
+            Ok(ents) => *entries = Some(ents),
+            Err(..) => return None,
+          }
         }
 
-        let mut ents =
-          entries.take().unwrap_or_else(|| readdir(path).unwrap());
+        let depth = entry.depth() + 1;
 
-        'outer: while let Some(entry) = ents.pop() {
-          if !pattern.matches_path(&entry) {
+        loop {
+          if let Some(ent) = current.take() {
+            match successor.select_from(fs, &ent, filter) {
+              None => (),
+              matched => {
+                *current = Some(ent);
+                return matched;
+              },
+            }
+          }
+
+          let (path, kind) = match entries.as_mut().unwrap().next() {
+            Some(next) => next,
+            None => {
+              *entries = None;
+              return None;
+            }
+          };
+
+          if !pattern.matches_path(&path) {
             continue;
           }
 
+          let ent = GlobEntry::with_kind(path, depth, kind);
+
           // this is necessary, otherwise the successor.select_from
           // would keep yielding Some(x) if the successor is Terminating
           if successor.is_terminating() {
-            if is_dir && !entry.is_dir() {
-              return None;
+            if filter.accepts(fs, &ent) {
+              return Some(ent);
             }
 
-            *entries = Some(ents);
-            return Some(entry);
+            continue;
           }
 
-          match successor.select_from(&entry, is_dir) {
-            None => continue 'outer,
-            matched => {
-              ents.push(entry);
-              *entries = Some(ents);
-              return matched;
-            },
-          }
+          *current = Some(ent);
         }
-
-        return None;
       },
 
       // TODO: currently doesn't consider cur-dir
       Recursive {
         successor: ref mut successor,
         ref mut directories,
+        ref mut current,
       } => {
-        if !path.is_dir() {
-          return None;
-        }
+        if directories.is_none() {
+          if !entry.is_dir_in(fs) {
+            return None;
+          }
 
-        let mut dirs =
-          directories.take()
-            .unwrap_or_else(|| walk_dir(path).unwrap().peekable());
+          *directories = Some(walk_dir(entry));
+        }
 
         loop {
-          if !dirs.peek().is_some() {
-            return None;
+          if let Some(dir) = current.take() {
+            match successor.select_from(fs, &dir, filter) {
+              None => (),
+              matched => {
+                *current = Some(dir);
+                return matched;
+              }
+            }
           }
 
+          let dir = match directories.as_mut().unwrap().next_in(fs) {
+            Some(dir) => dir,
+            None => {
+              *directories = None;
+              return None;
+            }
+          };
+
           // TODO:
           // this is returning only the directories,
           // like python, ruby, and zsh seems to do
           if successor.is_terminating() {
-            let path = dirs.next();
-            *directories = Some(dirs);
-            return path;
-          }
-
-          match successor.select_from(dirs.peek().unwrap(), is_dir) {
-            None => {
-              dirs.next();
-              continue;
-            },
-            matched => {
-              *directories = Some(dirs);
-              return matched;
+            if filter.accepts(fs, &dir) {
+              return Some(dir);
             }
+
+            continue;
           }
+
+          *current = Some(dir);
         }
       },
 
@@ -219,8 +213,8 @@ impl Selector {
         } else {
           *terminated = true;
 
-          if !is_dir || (is_dir && path.is_dir()) {
-            return Some(path.clone());
+          if filter.accepts(fs, entry) {
+            return Some(entry.clone());
           } else {
             return None;
           }
@@ -230,87 +224,180 @@ impl Selector {
   }
 }
 
+// a pre-order walk over a directory and all of its subdirectories,
+// holding one open listing per level rather than every pending directory
 struct Directories {
-  stack: Vec<Path>,
+  root: Option<GlobEntry>,
+  stack: Vec<(DirEntries, usize)>,
 }
 
-impl Iterator for Directories {
-  type Item = Path;
+impl Directories {
+  fn descend<F: FileSystem>(&mut self, fs: &F, entry: &GlobEntry) {
+    if let Ok(ents) = fs.read_dir(entry.path()) {
+      self.stack.push((ents, entry.depth() + 1));
+    }
+  }
+
+  fn next_in<F: FileSystem>(&mut self, fs: &F) -> Option<GlobEntry> {
+    if let Some(root) = self.root.take() {
+      self.descend(fs, &root);
+      return Some(root);
+    }
 
-  fn next(&mut self) -> Option<Path> {
-    match self.stack.pop() {
-      Some(path) => {
-        match readdir(&path) {
-          Ok(dirs) => {
-            self.stack.extend(dirs.into_iter().filter(|p| p.is_dir()));
+    loop {
+      let next = match self.stack.last_mut() {
+        Some(&mut (ref mut ents, depth)) =>
+          ents.next().map(|(path, kind)| GlobEntry::with_kind(path, depth, kind)),
+        None => return None,
+      };
+
+      match next {
+        Some(entry) => {
+          if entry.is_dir_in(fs) {
+            self.descend(fs, &entry);
+            return Some(entry);
           }
-          Err(..) => {}
         }
-
-        Some(path)
+        None => {
+          self.stack.pop();
+        }
       }
-      None => None
     }
   }
 }
 
-fn walk_dir(path: &Path) -> ::std::io::IoResult<Directories> {
-  Ok(Directories { stack: vec![path.clone()] })
+fn walk_dir(entry: &GlobEntry) -> Directories {
+  Directories { root: Some(entry.clone()), stack: Vec::new() }
 }
 
-pub struct Paths {
-  scope: Path,
+pub struct Paths<F: FileSystem = OsFs> {
+  fs: F,
+  pattern: String,
+  scope: GlobEntry,
   selector: Selector,
-  is_dir: bool,
+  filter: Filter,
+
+  // every match, sorted and sliced, when the options call for it
+  buffered: Option<vec::IntoIter<GlobEntry>>,
 }
 
 pub fn glob(pattern: &str) -> Result<Paths, Error> {
-  #[cfg(windows)]
-  fn check_windows_verbatim(p: &Path) -> bool { path::windows::is_verbatim(p) }
-  #[cfg(not(windows))]
-  fn check_windows_verbatim(_: &Path) -> bool { false }
+  glob_with(pattern, &GlobOptions::new())
+}
 
-  #[cfg(windows)]
-  fn handle_volume_relative(p: Path) -> Path {
-    use std::os::getcwd;
+/// Like `glob`, but only yields entries accepted by `options`.
+///
+/// A trailing zsh-style qualifier group, e.g. `**/*(.om)`, further
+/// refines `options`; see the `qualifiers` module.
+pub fn glob_with(pattern: &str, options: &GlobOptions) -> Result<Paths, Error> {
+  glob_in(OsFs, pattern, options)
+}
 
-    if path::windows::is_vol_relative(&p) {
-      getcwd().unwrap().push(p);
-    } else {
-      p
+/// Like `glob_with`, over any `FileSystem`: a `MemoryFs`, an archive, or a
+/// wrapper around `OsFs` that counts or fails calls.
+///
+/// Relative patterns start from `.` and absolute ones from the root, as
+/// `fs` understands them.
+pub fn glob_in<F: FileSystem>(fs: F, pattern: &str, options: &GlobOptions)
+                              -> Result<Paths<F>, Error> {
+  let plan = try!(Plan::new(pattern, options));
+
+  Ok(Paths {
+    fs: fs,
+    pattern: plan.pattern,
+    scope: GlobEntry::new(plan.scope, 0),
+    selector: Selector::from_components(plan.components),
+    filter: plan.filter,
+    buffered: None,
+  })
+}
+
+impl<F: FileSystem> Paths<F> {
+  /// Pairs each entry with what the pattern's wildcards matched in its
+  /// path, as `Pattern::captures` would give them. Paths that aren't UTF-8
+  /// come without captures.
+  pub fn captures(self) -> CapturedPaths<F> {
+    let pattern = Pattern::with_style(self.pattern.as_slice(), self.filter.options.style);
+
+    CapturedPaths {
+      pattern: pattern.ok().expect("pattern was already parsed"),
+      paths: self,
     }
   }
-  #[cfg(not(windows))]
-  fn handle_volume_relative(p: Path) -> Path { p }
+}
+
+impl<F: FileSystem> Paths<F> {
+  /// Pairs each entry with its path rewritten through `template`; see the
+  /// `rewrite` module. Paths that aren't UTF-8 aren't rewritten.
+  ///
+  /// Fails if the template refers to a wildcard the pattern doesn't have.
+  pub fn rewrite(self, template: &str) -> Result<RewrittenPaths<F>, Error> {
+    let captured = self.captures();
+    let template = try!(Template::new(template, &captured.pattern));
+
+    Ok(RewrittenPaths {
+      captured: captured,
+      template: template,
+    })
+  }
+}
 
-  let root = Path::new(pattern).root_path();
-  let root_len = root.as_ref().map_or(0us, |p| p.as_vec().len());
+pub struct RewrittenPaths<F: FileSystem = OsFs> {
+  captured: CapturedPaths<F>,
+  template: Template,
+}
+
+impl<F: FileSystem> Iterator for RewrittenPaths<F> {
+  type Item = (GlobEntry, Option<Path>);
 
-  if root.is_some() && check_windows_verbatim(root.as_ref().unwrap()) {
-    panic!("FIXME: verbatim");
+  fn next(&mut self) -> Option<(GlobEntry, Option<Path>)> {
+    let template = &self.template;
+
+    self.captured.next().map(|(entry, captures)| {
+      (entry, captures.map(|captures| Path::new(template.fill(&captures))))
+    })
   }
+}
 
-  let scope =
-    root
-      .map(handle_volume_relative)
-      .unwrap_or_else(|| Path::new("."));
+pub struct CapturedPaths<F: FileSystem = OsFs> {
+  paths: Paths<F>,
+  pattern: Pattern,
+}
 
-  let trimmed = pattern.slice_from(min(root_len, pattern.len()));
-  let selector = try!(Selector::from_pattern(trimmed));
-  let is_dir = pattern.chars().next_back().map(is_sep) == Some(true);
+impl<F: FileSystem> Iterator for CapturedPaths<F> {
+  type Item = (GlobEntry, Option<Captures>);
 
-  Ok(Paths {
-    scope: scope,
-    selector: selector,
-    is_dir: is_dir,
-  })
+  fn next(&mut self) -> Option<(GlobEntry, Option<Captures>)> {
+    self.paths.next().map(|entry| {
+      let captures = entry.path().as_str().and_then(|path| {
+        // a trailing `**` matches the directory it's in, too
+        self.pattern.captures(path).or_else(|| self.pattern.captures(format!("{}/", path).as_slice()))
+      });
+
+      (entry, captures)
+    })
+  }
 }
 
-impl Iterator for Paths {
-  type Item = Path;
+impl<F: FileSystem> Iterator for Paths<F> {
+  type Item = GlobEntry;
+
+  fn next(&mut self) -> Option<GlobEntry> {
+    if !self.filter.options.is_buffered() {
+      return self.selector.select_from(&self.fs, &self.scope, &self.filter);
+    }
+
+    if self.buffered.is_none() {
+      let mut entries = Vec::new();
+
+      while let Some(entry) = self.selector.select_from(&self.fs, &self.scope, &self.filter) {
+        entries.push(entry);
+      }
 
-  fn next(&mut self) -> Option<Path> {
-    return self.selector.select_from(&self.scope, self.is_dir);
+      self.buffered = Some(self.filter.options.arrange_in(&self.fs, entries).into_iter());
+    }
+
+    self.buffered.as_mut().unwrap().next()
   }
 }
 
@@ -353,6 +440,136 @@ mod test {
     assert!(glob(root_with_device.as_str().unwrap()).unwrap().next().is_some());
   }
 
+  #[test]
+  fn entry_depth() {
+    use filesystem::OsFs;
+
+    for entry in glob("/*/*").unwrap().take(10) {
+      assert_eq!(entry.depth(), 2);
+      assert!(entry.file_kind_in(&OsFs).is_some());
+    }
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_utf8_names() {
+    use std::io::{File, TempDir};
+
+    let root = TempDir::new("glob-bytes").unwrap();
+    let latin1 = root.path().join(b"caf\xe9.txt");
+    File::create(&latin1).unwrap();
+
+    let pattern = root.path().join("caf*.txt");
+    let found: Vec<Path> = glob(pattern.as_str().unwrap()).unwrap()
+      .map(|e| e.into_path())
+      .collect();
+
+    assert_eq!(found, vec![latin1]);
+  }
+
+  #[test]
+  fn windows_style() {
+    use memfs::MemoryFs;
+    use options::GlobOptions;
+    use many::glob_many_in;
+    use pattern::PathStyle;
+    use super::glob_in;
+
+    let fs = MemoryFs::from_paths([
+      "C:/Users/bob/AppData/Local/Temp/a.dmp",
+      "C:/Users/amy/AppData/Roaming/b.dmp",
+      "C:/Users/amy/Documents/c.dmp",
+    ].iter().map(|p| *p));
+
+    let options = GlobOptions::new().path_style(PathStyle::Windows);
+    let mut found: Vec<Path> =
+      glob_in(&fs, r"\\?\c:\Users\*\AppData\**\*.DMP", &options).unwrap()
+        .map(|e| e.into_path())
+        .collect();
+
+    found.sort();
+    assert_eq!(found, vec![
+      Path::new("C:/Users/amy/AppData/Roaming/b.dmp"),
+      Path::new("C:/Users/bob/AppData/Local/Temp/a.dmp"),
+    ]);
+
+    // literal components ignore case too, even on a case-sensitive tree
+    let lower = r"C:\users\*\appdata\**\*.dmp";
+    let mut found: Vec<Path> =
+      glob_in(&fs, lower, &options).unwrap().map(|e| e.into_path()).collect();
+
+    found.sort();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0], Path::new("C:/Users/amy/AppData/Roaming/b.dmp"));
+
+    let many: Vec<Path> =
+      glob_many_in(&fs, &[lower, r"c:\USERS\amy\documents\*"], &options).unwrap()
+        .map(|m| m.entry.into_path())
+        .collect();
+
+    assert_eq!(many.len(), 3);
+  }
+
+  #[test]
+  fn captures() {
+    use memfs::MemoryFs;
+    use options::GlobOptions;
+    use super::glob_in;
+
+    let fs = MemoryFs::from_paths([
+      "crates/glob/Cargo.toml",
+      "crates/regex/Cargo.toml",
+      "crates/regex/src/lib.rs",
+    ].iter().map(|p| *p));
+
+    let mut found: Vec<(String, String)> =
+      glob_in(&fs, "crates/{crate:*}/Cargo.toml", &GlobOptions::new()).unwrap()
+        .captures()
+        .map(|(_, caps)| {
+          let caps = caps.unwrap();
+          (caps.name("crate").unwrap().to_string(), caps.get(0).unwrap().to_string())
+        })
+        .collect();
+
+    found.sort();
+    assert_eq!(found, vec![
+      ("glob".to_string(), "glob".to_string()),
+      ("regex".to_string(), "regex".to_string()),
+    ]);
+
+    let mut dirs: Vec<String> = glob_in(&fs, "crates/regex/**/", &GlobOptions::new()).unwrap()
+      .captures()
+      .map(|(_, caps)| caps.unwrap().get(0).unwrap().to_string())
+      .collect();
+
+    // the directory itself is in there, for a trailing `**`
+    dirs.sort();
+    assert_eq!(dirs, vec!["", "src"]);
+  }
+
+  #[test]
+  fn rewrite() {
+    use memfs::MemoryFs;
+    use options::GlobOptions;
+    use super::glob_in;
+
+    let fs = MemoryFs::from_paths(["src/main.c", "src/net/tcp.c", "src/net/tcp.h"].iter().map(|p| *p));
+
+    let mut rewritten: Vec<(Path, Path)> =
+      glob_in(&fs, "src/**/*.c", &GlobOptions::new()).unwrap()
+        .rewrite("build/#1/#2.o").unwrap()
+        .map(|(entry, dest)| (entry.into_path(), dest.unwrap()))
+        .collect();
+
+    rewritten.sort();
+    assert_eq!(rewritten, vec![
+      (Path::new("src/main.c"), Path::new("build/main.o")),
+      (Path::new("src/net/tcp.c"), Path::new("build/net/tcp.o")),
+    ]);
+
+    assert!(glob_in(&fs, "src/*.c", &GlobOptions::new()).unwrap().rewrite("#2").is_err());
+  }
+
   #[test]
   fn lots_of_files() {
     // TODO: this comes up with a perm denied file
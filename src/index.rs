@@ -0,0 +1,312 @@
+//! An index of a fixed set of paths, for running many globs against it.
+//!
+//! Paths are stored as a trie of their components, and queries walk it
+//! with the same selectors as on disk, so `src/*/mod.rs` only looks at the
+//! children of `src` rather than testing every path in the set.
+//! Directories are implied by the paths below them: a path is a directory
+//! if any other path is below it, and a file otherwise.
+//!
+//! ```ignore
+//! let index: PathIndex = manifest.lines().map(Path::new).collect();
+//!
+//! for entry in index.glob("src/**/*.rs", &GlobOptions::new()).unwrap() {
+//!   println!("{}", entry.path().display());
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::io::{IoError, IoResult};
+use std::io;
+use std::iter::FromIterator;
+
+use entry::{FileKind, Metadata};
+use filesystem::{FileSystem, DirEntries};
+use glob::{Paths, glob_in};
+use options::GlobOptions;
+use pattern::Error;
+
+#[derive(Clone)]
+struct Node {
+  children: BTreeMap<Vec<u8>, Node>,
+  // the path ending here was inserted, rather than only implied
+  inserted: bool,
+}
+
+impl Node {
+  fn new() -> Node {
+    Node {
+      children: BTreeMap::new(),
+      inserted: false,
+    }
+  }
+
+  fn kind(&self) -> FileKind {
+    if self.children.is_empty() { FileKind::File } else { FileKind::Directory }
+  }
+
+  // removes the path below this node, pruning the directories it leaves
+  // empty, and returns whether it was there
+  fn remove(&mut self, components: &[&[u8]]) -> bool {
+    if components.is_empty() {
+      let was = self.inserted;
+      self.inserted = false;
+      return was;
+    }
+
+    let (removed, prune) = match self.children.get_mut(components[0]) {
+      Some(child) => {
+        let removed = child.remove(components.slice_from(1));
+        (removed, !child.inserted && child.children.is_empty())
+      }
+      None => (false, false),
+    };
+
+    if prune {
+      self.children.remove(components[0]);
+    }
+
+    removed
+  }
+}
+
+#[derive(Clone)]
+pub struct PathIndex {
+  root: Node,
+  len: usize,
+}
+
+fn not_found(path: &Path) -> IoError {
+  IoError {
+    kind: io::FileNotFound,
+    desc: "path not in index",
+    detail: Some(path.display().to_string()),
+  }
+}
+
+// a path's components, without `.`, with `..` applied where it can be,
+// or `None` if it climbs above the root
+fn normalized(path: &Path) -> Option<Vec<&[u8]>> {
+  let mut components = Vec::new();
+
+  for component in path.components() {
+    if component == b".." {
+      if components.pop().is_none() {
+        return None;
+      }
+    } else if component != b"." {
+      components.push(component);
+    }
+  }
+
+  Some(components)
+}
+
+impl PathIndex {
+  pub fn new() -> PathIndex {
+    PathIndex {
+      root: Node::new(),
+      len: 0,
+    }
+  }
+
+  /// Adds a path and the directories above it, returning whether it's new.
+  pub fn insert(&mut self, path: &Path) -> bool {
+    let components = match normalized(path) {
+      Some(components) => components,
+      None => return false,
+    };
+
+    if components.is_empty() {
+      return false;
+    }
+
+    let mut node = &mut self.root;
+
+    for component in components.into_iter() {
+      let current = node;
+      node = match current.children.entry(component.to_vec()) {
+        Entry::Occupied(e) => e.into_mut(),
+        Entry::Vacant(e) => e.insert(Node::new()),
+      };
+    }
+
+    if node.inserted {
+      return false;
+    }
+
+    node.inserted = true;
+    self.len += 1;
+    true
+  }
+
+  /// Removes a path, along with any directories only it implied, returning
+  /// whether it was there. Paths below it stay.
+  pub fn remove(&mut self, path: &Path) -> bool {
+    let removed = match normalized(path) {
+      Some(ref components) if !components.is_empty() => {
+        self.root.remove(components.as_slice())
+      }
+      _ => false,
+    };
+
+    if removed {
+      self.len -= 1;
+    }
+
+    removed
+  }
+
+  /// Whether the path was inserted, as opposed to only being implied by
+  /// the paths below it.
+  pub fn contains(&self, path: &Path) -> bool {
+    self.find(path).map_or(false, |node| node.inserted)
+  }
+
+  /// The number of paths inserted.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Globs the indexed paths; relative and absolute patterns both start
+  /// from the root of the index.
+  pub fn glob<'a>(&'a self, pattern: &str, options: &GlobOptions)
+                  -> Result<Paths<&'a PathIndex>, Error> {
+    glob_in(self, pattern, options)
+  }
+
+  fn find(&self, path: &Path) -> Option<&Node> {
+    let components = match normalized(path) {
+      Some(components) => components,
+      None => return None,
+    };
+
+    let mut node = &self.root;
+
+    for component in components.into_iter() {
+      node = match node.children.get(component) {
+        Some(child) => child,
+        None => return None,
+      };
+    }
+
+    Some(node)
+  }
+}
+
+impl FromIterator<Path> for PathIndex {
+  fn from_iter<I: Iterator<Item=Path>>(paths: I) -> PathIndex {
+    let mut index = PathIndex::new();
+
+    for path in paths {
+      index.insert(&path);
+    }
+
+    index
+  }
+}
+
+impl FileSystem for PathIndex {
+  fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+    let node = try!(self.find(path).ok_or_else(|| not_found(path)));
+
+    let entries: Vec<(Path, Option<FileKind>)> = node.children.iter()
+      .map(|(name, child)| (path.join(name.as_slice()), Some(child.kind())))
+      .collect();
+
+    Ok(Box::new(entries.into_iter()) as DirEntries)
+  }
+
+  fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+    self.find(path).map(|node| node.kind()).ok_or_else(|| not_found(path))
+  }
+
+  fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+    let kind = try!(self.file_kind(path));
+
+    Ok(Metadata {
+      kind: kind,
+      size: 0,
+      modified: 0,
+      permissions: if kind == FileKind::Directory { 0o755 } else { 0o644 },
+      uid: 0,
+      gid: 0,
+    })
+  }
+
+  fn read_link(&self, path: &Path) -> IoResult<Path> {
+    Err(IoError {
+      kind: io::InvalidInput,
+      desc: "the index has no symbolic links",
+      detail: Some(path.display().to_string()),
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::PathIndex;
+  use options::GlobOptions;
+
+  fn index() -> PathIndex {
+    ["Cargo.toml", "src/lib.rs", "src/glob.rs", "src/bin/main.rs", "tests/glob-std.rs"]
+      .iter()
+      .map(|p| Path::new(*p))
+      .collect()
+  }
+
+  fn glob(index: &PathIndex, pattern: &str) -> Vec<String> {
+    index.glob(pattern, &GlobOptions::new()).unwrap()
+      .map(|e| e.path().display().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn queries() {
+    let index = index();
+
+    assert_eq!(index.len(), 5);
+    assert_eq!(glob(&index, "src/*.rs"), vec!["src/glob.rs", "src/lib.rs"]);
+    assert_eq!(glob(&index, "**/*.rs").len(), 4);
+    assert_eq!(glob(&index, "*/"), vec!["src", "tests"]);
+    assert_eq!(glob(&index, "/src/bin/main.rs"), vec!["/src/bin/main.rs"]);
+    assert_eq!(glob(&index, "../*"), Vec::<String>::new());
+  }
+
+  #[test]
+  fn insert_and_remove() {
+    let mut index = index();
+
+    assert!(!index.insert(&Path::new("./src/lib.rs")));
+    assert!(index.insert(&Path::new("src/bin/extra.rs")));
+    assert_eq!(glob(&index, "src/bin/*"), vec!["src/bin/extra.rs", "src/bin/main.rs"]);
+
+    assert!(index.remove(&Path::new("src/bin/main.rs")));
+    assert!(index.remove(&Path::new("src/bin/extra.rs")));
+    assert!(!index.remove(&Path::new("src/bin/extra.rs")));
+
+    // `src/bin` went with the last path below it
+    assert_eq!(glob(&index, "src/*"), vec!["src/glob.rs", "src/lib.rs"]);
+    assert!(!index.contains(&Path::new("src")));
+    assert_eq!(index.len(), 3);
+  }
+
+  #[test]
+  fn inserted_directories() {
+    let mut index = index();
+
+    assert!(index.insert(&Path::new("src")));
+    assert!(index.contains(&Path::new("src")));
+
+    // still there as a path of its own once emptied
+    index.remove(&Path::new("src/lib.rs"));
+    index.remove(&Path::new("src/glob.rs"));
+    index.remove(&Path::new("src/bin/main.rs"));
+    assert_eq!(glob(&index, "src"), vec!["src"]);
+    assert!(!index.remove(&Path::new("..")));
+  }
+}
@@ -0,0 +1,380 @@
+//! Globbing flat keyspaces, like object store buckets.
+//!
+//! Object stores have no directories, only keys and a listing call that
+//! takes a prefix and, optionally, a delimiter to group deeper keys by. The
+//! walker here plans its listings around that:
+//!
+//! * literal components only extend the prefix, costing no calls at all
+//! * each wildcard component lists one level, with `/` as the delimiter
+//! * `**` lists everything below the current prefix in a single call, and
+//!   the rest of the pattern is matched against the keys that come back
+//!
+//! Keys are yielded as they are; "directories", the common prefixes of
+//! deeper keys, are yielded with their trailing `/`. As on disk, a pattern
+//! ending in `/` only yields directories.
+
+use std::cell::Cell;
+use std::collections::{BTreeSet, RingBuf};
+use std::collections::Bound::{Included, Unbounded};
+use std::io::IoResult;
+
+use pattern::{Error, PathStyle};
+use plan::{self, Component};
+
+/// The result of a listing call.
+#[derive(Clone, Show, PartialEq)]
+pub struct Listing {
+  /// Keys starting with the prefix, and not containing the delimiter past it.
+  pub keys: Vec<String>,
+  /// The distinct prefixes of the remaining keys, up to and including the
+  /// first delimiter past the prefix.
+  pub prefixes: Vec<String>,
+}
+
+pub trait Keyspace {
+  /// Lists the keys starting with `prefix`, grouping those with
+  /// `delimiter` past the prefix when one is given, in ascending order.
+  fn list(&self, prefix: &str, delimiter: Option<char>) -> IoResult<Listing>;
+}
+
+impl<'a, K: Keyspace> Keyspace for &'a K {
+  fn list(&self, prefix: &str, delimiter: Option<char>) -> IoResult<Listing> {
+    (**self).list(prefix, delimiter)
+  }
+}
+
+/// A keyspace held in memory, listing the way object stores do. It counts
+/// the listing calls made on it.
+pub struct MemoryKeyspace {
+  keys: BTreeSet<String>,
+  calls: Cell<usize>,
+}
+
+impl MemoryKeyspace {
+  pub fn new<'a, I: Iterator<Item=&'a str>>(keys: I) -> MemoryKeyspace {
+    MemoryKeyspace {
+      keys: keys.map(|k| k.to_string()).collect(),
+      calls: Cell::new(0),
+    }
+  }
+
+  pub fn insert(&mut self, key: &str) {
+    self.keys.insert(key.to_string());
+  }
+
+  pub fn remove(&mut self, key: &str) {
+    self.keys.remove(key);
+  }
+
+  /// The number of listing calls made so far.
+  pub fn calls(&self) -> usize {
+    self.calls.get()
+  }
+}
+
+impl Keyspace for MemoryKeyspace {
+  fn list(&self, prefix: &str, delimiter: Option<char>) -> IoResult<Listing> {
+    self.calls.set(self.calls.get() + 1);
+
+    let mut listing = Listing { keys: Vec::new(), prefixes: Vec::new() };
+    let start = prefix.to_string();
+
+    for key in self.keys.range(Included(&start), Unbounded) {
+      if !key.starts_with(prefix) {
+        break;
+      }
+
+      let rest = key.slice_from(prefix.len());
+
+      match delimiter.and_then(|d| rest.find(d)) {
+        Some(i) => {
+          let common = key.slice_to(prefix.len() + i + 1).to_string();
+
+          if listing.prefixes.last() != Some(&common) {
+            listing.prefixes.push(common);
+          }
+        }
+        None => listing.keys.push(key.clone()),
+      }
+    }
+
+    Ok(listing)
+  }
+}
+
+// whether `components` match the rest of `key` from `start`, with `ends`
+// the ends of its remaining segments; `**` may swallow a key's last
+// segment only when the key is a directory
+fn matches(components: &[Component], key: &str, start: usize, ends: &[usize], dir: bool) -> bool {
+  if components.is_empty() {
+    return ends.is_empty();
+  }
+
+  match components[0] {
+    Component::Recursive => {
+      matches(components.slice_from(1), key, start, ends, dir) ||
+        (ends.len() > 1 || (dir && ends.len() == 1)) &&
+        matches(components, key, ends[0] + 1, ends.slice_from(1), dir)
+    }
+    _ if ends.is_empty() => false,
+    Component::Precise(ref name) => {
+      key.slice(start, ends[0]) == name.as_slice() &&
+        matches(components.slice_from(1), key, ends[0] + 1, ends.slice_from(1), dir)
+    }
+    Component::Wildcard(ref pattern) => {
+      pattern.matches(key.slice(start, ends[0])) &&
+        matches(components.slice_from(1), key, ends[0] + 1, ends.slice_from(1), dir)
+    }
+  }
+}
+
+pub struct KeyPaths<K> {
+  keyspace: K,
+  components: Vec<Component>,
+  // the pattern ended in a separator
+  is_dir: bool,
+
+  // prefixes left to expand, ending in `/` unless empty, each with the
+  // index of the component to apply below it
+  stack: Vec<(String, usize)>,
+  ready: RingBuf<String>,
+}
+
+impl<K: Keyspace> KeyPaths<K> {
+  fn offer(&mut self, key: String) {
+    if !self.is_dir || key.ends_with("/") {
+      self.ready.push_back(key);
+    }
+  }
+
+  fn expand(&mut self, mut prefix: String, mut index: usize) {
+    // literal components cost nothing until the last one
+    while index + 1 < self.components.len() {
+      match self.components[index] {
+        Component::Precise(ref name) => {
+          prefix.push_str(name.as_slice());
+          prefix.push('/');
+        }
+        _ => break,
+      }
+
+      index += 1;
+    }
+
+    let (found, below) = match self.select(prefix, index) {
+      Ok(selected) => selected,
+      Err(..) => return,
+    };
+
+    for key in found.into_iter() {
+      self.offer(key);
+    }
+
+    // expand the first directory first
+    self.stack.extend(below.into_iter().rev().map(|dir| (dir, index + 1)));
+  }
+
+  // the keys matched by the component at `index` below `prefix`, and the
+  // directories to apply the next one to
+  fn select(&self, prefix: String, index: usize) -> IoResult<(Vec<String>, Vec<String>)> {
+    let last = index + 1 == self.components.len();
+    let mut found = Vec::new();
+    let mut below = Vec::new();
+
+    match self.components[index] {
+      Component::Precise(ref name) => {
+        let key = format!("{}{}", prefix, name);
+        let dir = format!("{}/", key);
+        let listing = try!(self.keyspace.list(key.as_slice(), Some('/')));
+
+        if listing.keys.iter().any(|k| *k == key) {
+          found.push(key);
+        }
+
+        if listing.prefixes.iter().any(|p| *p == dir) {
+          found.push(dir);
+        }
+      }
+
+      Component::Wildcard(ref pattern) => {
+        let Listing { keys, prefixes } = try!(self.keyspace.list(prefix.as_slice(), Some('/')));
+
+        // patterns only anchor at the end, so match just the part below
+        // the prefix
+        for dir in prefixes.into_iter() {
+          if pattern.matches(dir.slice(prefix.len(), dir.len() - 1)) {
+            if last { found.push(dir) } else { below.push(dir) }
+          }
+        }
+
+        if last {
+          found.extend(keys.into_iter().filter(|k| pattern.matches(k.slice_from(prefix.len()))));
+        }
+      }
+
+      Component::Recursive => {
+        let listing = try!(self.keyspace.list(prefix.as_slice(), None));
+        let rest = self.components.slice_from(index);
+
+        // the separators past the prefix
+        let separators = |&: key: &str| -> Vec<usize> {
+          key.char_indices()
+            .filter(|&(i, c)| i >= prefix.len() && c == '/')
+            .map(|(i, _)| i)
+            .collect()
+        };
+
+        let mut dirs = BTreeSet::new();
+
+        if !prefix.is_empty() && !listing.keys.is_empty() {
+          dirs.insert(prefix.clone());
+        }
+
+        for key in listing.keys.iter() {
+          let mut ends = separators(key.as_slice());
+
+          for &end in ends.iter() {
+            dirs.insert(key.slice_to(end + 1).to_string());
+          }
+
+          ends.push(key.len());
+
+          if matches(rest, key.as_slice(), prefix.len(), ends.as_slice(), false) {
+            found.push(key.clone());
+          }
+        }
+
+        for dir in dirs.into_iter() {
+          let ends = separators(dir.as_slice());
+
+          if matches(rest, dir.as_slice(), prefix.len(), ends.as_slice(), true) {
+            found.push(dir);
+          }
+        }
+
+        found.sort();
+      }
+    }
+
+    Ok((found, below))
+  }
+}
+
+impl<K: Keyspace> Iterator for KeyPaths<K> {
+  type Item = String;
+
+  fn next(&mut self) -> Option<String> {
+    loop {
+      if let Some(key) = self.ready.pop_front() {
+        return Some(key);
+      }
+
+      match self.stack.pop() {
+        Some((prefix, index)) => self.expand(prefix, index),
+        None => return None,
+      }
+    }
+  }
+}
+
+/// Globs the keys of `keyspace`, taking `/` as the delimiter.
+///
+/// Keys have no root, so a leading `/` is just part of the first component,
+/// and qualifiers aren't supported.
+pub fn glob_keys<K: Keyspace>(keyspace: K, pattern: &str) -> Result<KeyPaths<K>, Error> {
+  let mut components = try!(plan::components(pattern, PathStyle::Posix));
+  let is_dir = pattern.ends_with("/");
+
+  // the empty component after the trailing separator
+  if is_dir && components.len() > 1 {
+    components.pop();
+  }
+
+  Ok(KeyPaths {
+    keyspace: keyspace,
+    components: components,
+    is_dir: is_dir,
+    stack: vec![(String::new(), 0)],
+    ready: RingBuf::new(),
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Keyspace, Listing, MemoryKeyspace, glob_keys};
+
+  fn bucket() -> MemoryKeyspace {
+    MemoryKeyspace::new([
+      "builds/1/app.tar.gz",
+      "builds/1/logs/build.log",
+      "builds/2/app.tar.gz",
+      "builds/2/logs/build.log",
+      "builds/2/logs/test.log",
+      "builds/latest",
+      "readme",
+    ].iter().map(|k| *k))
+  }
+
+  fn glob(keyspace: &MemoryKeyspace, pattern: &str) -> Vec<String> {
+    let mut keys: Vec<String> = glob_keys(keyspace, pattern).unwrap().collect();
+    keys.sort();
+    keys
+  }
+
+  #[test]
+  fn listing() {
+    let bucket = bucket();
+
+    assert_eq!(bucket.list("builds/", Some('/')).unwrap(), Listing {
+      keys: vec!["builds/latest".to_string()],
+      prefixes: vec!["builds/1/".to_string(), "builds/2/".to_string()],
+    });
+
+    assert_eq!(bucket.list("builds/2/logs", None).unwrap().keys.len(), 2);
+  }
+
+  #[test]
+  fn literal_prefixes() {
+    let bucket = bucket();
+
+    assert_eq!(glob(&bucket, "builds/2/logs/*.log"),
+               vec!["builds/2/logs/build.log", "builds/2/logs/test.log"]);
+    assert_eq!(bucket.calls(), 1);
+
+    assert_eq!(glob(&bucket, "builds/latest"), vec!["builds/latest"]);
+    assert_eq!(glob(&bucket, "builds/2"), vec!["builds/2/"]);
+    assert_eq!(glob(&bucket, "builds/nope"), Vec::<String>::new());
+  }
+
+  #[test]
+  fn wildcards() {
+    let bucket = bucket();
+
+    assert_eq!(glob(&bucket, "builds/*/app.tar.gz"),
+               vec!["builds/1/app.tar.gz", "builds/2/app.tar.gz"]);
+    assert_eq!(glob(&bucket, "builds/*"),
+               vec!["builds/1/", "builds/2/", "builds/latest"]);
+    assert_eq!(glob(&bucket, "builds/*/"), vec!["builds/1/", "builds/2/"]);
+
+    // the wildcard takes a whole segment, not the end of one
+    let bucket = MemoryKeyspace::new(["builds/12/app", "builds/3/app", "builds/45"].iter().map(|k| *k));
+    assert_eq!(glob(&bucket, "builds/?"), vec!["builds/3/"]);
+    assert_eq!(glob(&bucket, "builds/?/app"), vec!["builds/3/app"]);
+  }
+
+  #[test]
+  fn recursive() {
+    let bucket = bucket();
+
+    assert_eq!(glob(&bucket, "builds/**/*.log"), vec![
+      "builds/1/logs/build.log",
+      "builds/2/logs/build.log",
+      "builds/2/logs/test.log",
+    ]);
+    assert_eq!(bucket.calls(), 1);
+
+    assert_eq!(glob(&bucket, "**/logs/"), vec!["builds/1/logs/", "builds/2/logs/"]);
+    assert_eq!(glob(&bucket, "builds/2/**"),
+               vec!["builds/2/", "builds/2/logs/"]);
+  }
+}
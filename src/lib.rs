@@ -1,9 +1,42 @@
-#![feature(plugin)]
+#![feature(plugin, libc)]
 
+extern crate libc;
 extern crate regex;
+extern crate time;
+
+#[cfg(feature = "async")]
+extern crate futures;
+
+#[cfg(feature = "gzip")]
+extern crate flate2;
 
 #[plugin]
 extern crate regex_macros;
 
 pub mod pattern;
+pub mod ast;
+pub mod export;
+pub mod rewrite;
+pub mod set;
 pub mod glob;
+pub mod entry;
+pub mod filesystem;
+pub mod memfs;
+pub mod archive;
+pub mod tar;
+pub mod zip;
+pub mod keyspace;
+pub mod index;
+pub mod options;
+pub mod qualifiers;
+pub mod parallel;
+pub mod many;
+
+#[cfg(feature = "async")]
+pub mod stream;
+
+mod plan;
+mod readdir;
+
+#[cfg(test)]
+mod testing;
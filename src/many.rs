@@ -0,0 +1,373 @@
+//! Globbing many patterns in a single traversal.
+//!
+//! The patterns' components are merged into a trie, so patterns sharing a
+//! prefix share its nodes. The walk then tracks, for each directory, the
+//! set of trie nodes that could still match below it, and lists every
+//! directory at most once no matter how many patterns pass through it.
+
+use std::collections::{HashSet, RingBuf};
+
+use entry::GlobEntry;
+use filesystem::{FileSystem, OsFs};
+use options::GlobOptions;
+use pattern::{Error, PathStyle};
+use plan::{self, Plan, Component, Filter};
+
+/// A path matched by `glob_many`, along with the indices of the patterns
+/// that matched it, in ascending order.
+pub struct GlobMatch {
+  pub entry: GlobEntry,
+  pub patterns: Vec<usize>,
+}
+
+struct Node {
+  edges: Vec<(Component, usize)>,
+  // reached through `**`, so also matches any subdirectory
+  recursive: bool,
+  // patterns ending here
+  accepting: Vec<usize>,
+}
+
+impl Node {
+  fn new(recursive: bool) -> Node {
+    Node {
+      edges: Vec::new(),
+      recursive: recursive,
+      accepting: Vec::new(),
+    }
+  }
+}
+
+fn same_component(a: &Component, b: &Component, style: PathStyle) -> bool {
+  match (a, b) {
+    (&Component::Precise(ref a), &Component::Precise(ref b)) =>
+      plan::is_named(a.as_slice(), b.as_bytes(), style),
+    (&Component::Wildcard(ref a), &Component::Wildcard(ref b)) => a.as_str() == b.as_str(),
+    (&Component::Recursive, &Component::Recursive) => true,
+    _ => false,
+  }
+}
+
+pub struct ManyPaths<F: FileSystem = OsFs> {
+  fs: F,
+  nodes: Vec<Node>,
+  filters: Vec<Filter>,
+  style: PathStyle,
+  dedup: bool,
+  seen: HashSet<Path>,
+
+  // directories left to expand, each with the nodes active inside it
+  stack: Vec<(GlobEntry, Vec<usize>)>,
+  ready: RingBuf<GlobMatch>,
+}
+
+impl<F: FileSystem> ManyPaths<F> {
+  /// Yield a path again if it's reached a second time, e.g. through a
+  /// `..` component, rather than remembering every path yielded so far.
+  pub fn keep_duplicates(mut self) -> ManyPaths<F> {
+    self.dedup = false;
+    self
+  }
+
+  fn insert(&mut self, root: usize, components: Vec<Component>) -> usize {
+    let mut node = root;
+    let style = self.style;
+
+    for component in components.into_iter() {
+      let existing = self.nodes[node].edges.iter()
+        .find(|&&(ref c, _)| same_component(c, &component, style))
+        .map(|&(_, target)| target);
+
+      node = match existing {
+        Some(target) => target,
+        None => {
+          let target = self.nodes.len();
+          let recursive = match component { Component::Recursive => true, _ => false };
+          self.nodes.push(Node::new(recursive));
+          self.nodes[node].edges.push((component, target));
+          target
+        }
+      };
+    }
+
+    node
+  }
+
+  // adds the nodes reachable by `**` matching no components at all
+  fn closure(&self, mut states: Vec<usize>) -> Vec<usize> {
+    let mut i = 0;
+
+    while i < states.len() {
+      for &(ref component, target) in self.nodes[states[i]].edges.iter() {
+        if let Component::Recursive = *component {
+          if !states.contains(&target) {
+            states.push(target);
+          }
+        }
+      }
+
+      i += 1;
+    }
+
+    states.sort();
+    states.dedup();
+    states
+  }
+
+  fn offer(&mut self, entry: &GlobEntry, states: &Vec<usize>) {
+    let mut patterns = Vec::new();
+
+    for &state in states.iter() {
+      for &index in self.nodes[state].accepting.iter() {
+        if self.filters[index].accepts(&self.fs, entry) {
+          patterns.push(index);
+        }
+      }
+    }
+
+    if patterns.is_empty() {
+      return;
+    }
+
+    if self.dedup && !self.seen.insert(entry.path().clone()) {
+      return;
+    }
+
+    patterns.sort();
+    patterns.dedup();
+
+    self.ready.push_back(GlobMatch { entry: entry.clone(), patterns: patterns });
+  }
+
+  fn visit(&mut self, entry: GlobEntry, states: Vec<usize>) {
+    let states = self.closure(states);
+
+    if states.is_empty() {
+      return;
+    }
+
+    self.offer(&entry, &states);
+
+    let expandable = states.iter().any(|&s| {
+      let node = &self.nodes[s];
+      node.recursive || !node.edges.is_empty()
+    });
+
+    if expandable && entry.is_dir_in(&self.fs) {
+      self.stack.push((entry, states));
+    }
+  }
+
+  fn expand(&mut self, dir: GlobEntry, states: Vec<usize>) {
+    let depth = dir.depth() + 1;
+
+    let listed = states.iter().any(|&s| {
+      let node = &self.nodes[s];
+      node.recursive || node.edges.iter().any(|&(ref c, _)| {
+        match *c { Component::Wildcard(..) => true, _ => false }
+      })
+    });
+
+    let style = self.style;
+    let same_name = |&: a: &String, b: &String| plan::is_named(a.as_slice(), b.as_bytes(), style);
+    let mut precise: Vec<(String, Vec<usize>)> = Vec::new();
+
+    for &state in states.iter() {
+      for &(ref component, target) in self.nodes[state].edges.iter() {
+        if let Component::Precise(ref name) = *component {
+          match precise.iter().position(|&(ref n, _)| same_name(n, name)) {
+            Some(i) => precise[i].1.push(target),
+            None => precise.push((name.clone(), vec![target])),
+          }
+        }
+      }
+    }
+
+    let mut children = Vec::new();
+
+    if listed {
+      if let Ok(ents) = self.fs.read_dir(dir.path()) {
+        for (path, kind) in ents {
+          let child = GlobEntry::with_kind(path, depth, kind);
+          let mut next = Vec::new();
+
+          for &state in states.iter() {
+            let node = &self.nodes[state];
+
+            if node.recursive && child.is_dir_in(&self.fs) {
+              next.push(state);
+            }
+
+            for &(ref component, target) in node.edges.iter() {
+              if let Component::Wildcard(ref pattern) = *component {
+                if pattern.matches_path(child.path()) {
+                  next.push(target);
+                }
+              }
+            }
+          }
+
+          let found = child.path().filename().and_then(|filename| {
+            precise.iter().position(|&(ref name, _)| plan::is_named(name.as_slice(), filename, style))
+          });
+
+          if let Some(i) = found {
+            let (_, targets) = precise.remove(i);
+            next.extend(targets.into_iter());
+          }
+
+          if !next.is_empty() {
+            children.push((child, next));
+          }
+        }
+      }
+    }
+
+    // literal components that didn't come up in a listing, or that never
+    // will, like `.`, `..` and the empty component after a trailing slash
+    for (name, targets) in precise.into_iter() {
+      if let Some(joined) = plan::resolve(&self.fs, &dir, name.as_slice(), style) {
+        children.push((joined, targets));
+      }
+    }
+
+    for (child, next) in children.into_iter() {
+      self.visit(child, next);
+    }
+  }
+}
+
+impl<F: FileSystem> Iterator for ManyPaths<F> {
+  type Item = GlobMatch;
+
+  fn next(&mut self) -> Option<GlobMatch> {
+    loop {
+      if let Some(found) = self.ready.pop_front() {
+        return Some(found);
+      }
+
+      match self.stack.pop() {
+        Some((dir, states)) => self.expand(dir, states),
+        None => return None,
+      }
+    }
+  }
+}
+
+/// Globs every pattern in one traversal, yielding each matching path once.
+pub fn glob_many(patterns: &[&str]) -> Result<ManyPaths, Error> {
+  glob_many_with(patterns, &GlobOptions::new())
+}
+
+/// Like `glob_many`, applying `options` to every pattern.
+///
+/// Each pattern may carry its own qualifiers, but sorting and slicing only
+/// make sense for a single pattern and are ignored here.
+pub fn glob_many_with(patterns: &[&str], options: &GlobOptions) -> Result<ManyPaths, Error> {
+  glob_many_in(OsFs, patterns, options)
+}
+
+/// Like `glob_many_with`, in `fs`.
+pub fn glob_many_in<F: FileSystem>(fs: F, patterns: &[&str], options: &GlobOptions)
+                                   -> Result<ManyPaths<F>, Error> {
+  let mut many = ManyPaths {
+    fs: fs,
+    nodes: Vec::new(),
+    filters: Vec::new(),
+    style: options.style,
+    dedup: true,
+    seen: HashSet::new(),
+    stack: Vec::new(),
+    ready: RingBuf::new(),
+  };
+
+  let mut roots: Vec<(Path, usize)> = Vec::new();
+
+  for (index, pattern) in patterns.iter().enumerate() {
+    let plan = try!(Plan::new(*pattern, options));
+
+    let root = match roots.iter().find(|&&(ref scope, _)| *scope == plan.scope) {
+      Some(&(_, root)) => root,
+      None => {
+        let root = many.nodes.len();
+        many.nodes.push(Node::new(false));
+        roots.push((plan.scope.clone(), root));
+        root
+      }
+    };
+
+    let last = many.insert(root, plan.components);
+    many.nodes[last].accepting.push(index);
+    many.filters.push(plan.filter);
+  }
+
+  // expand the first scope first
+  for (scope, root) in roots.into_iter().rev() {
+    many.visit(GlobEntry::new(scope, 0), vec![root]);
+  }
+
+  Ok(many)
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+  use std::io::TempDir;
+
+  use super::glob_many;
+  use testing;
+
+  fn patterns(root: &TempDir, patterns: &[&str]) -> Vec<String> {
+    // joining would normalize away any `..`
+    patterns.iter()
+      .map(|p| format!("{}/{}", root.path().display(), p))
+      .collect()
+  }
+
+  fn tree() -> TempDir {
+    testing::tree("glob-many", &["a", "a/b", "c"], &["a/x.rs", "a/b/y.rs", "a/b/z.txt", "c/w.rs"])
+  }
+
+  #[test]
+  fn tags_patterns() {
+    let root = tree();
+    let pats = patterns(&root, &["**/*.rs", "a/**/*", "a/b/*.txt", "c/"]);
+    let pats: Vec<&str> = pats.iter().map(|p| p.as_slice()).collect();
+
+    let matches: HashMap<Path, Vec<usize>> =
+      glob_many(pats.as_slice()).unwrap()
+        .map(|m| (m.entry.into_path(), m.patterns))
+        .collect();
+
+    let at = |&: p: &str| matches.get(&root.path().join(p)).unwrap().clone();
+
+    assert_eq!(matches.len(), 6);
+    assert_eq!(at("a/x.rs"), vec![0, 1]);
+    assert_eq!(at("a/b/y.rs"), vec![0, 1]);
+    assert_eq!(at("a/b/z.txt"), vec![1, 2]);
+    assert_eq!(at("a/b"), vec![1]);
+    assert_eq!(at("c/w.rs"), vec![0]);
+    assert_eq!(at("c"), vec![3]);
+  }
+
+  #[test]
+  fn duplicates() {
+    let root = tree();
+
+    // the same file, reached through two different directories
+    let pats = patterns(&root, &["a/../c/*.rs", "c/w.rs"]);
+    let pats: Vec<&str> = pats.iter().map(|p| p.as_slice()).collect();
+
+    let matches: Vec<_> = glob_many(pats.as_slice()).unwrap().collect();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].entry.path(), &root.path().join("c/w.rs"));
+
+    let matches: Vec<_> = glob_many(pats.as_slice()).unwrap().keep_duplicates().collect();
+    assert_eq!(matches.len(), 2);
+  }
+
+  #[test]
+  fn errors() {
+    assert!(glob_many(&["a/*", "a/**b"]).is_err());
+  }
+}
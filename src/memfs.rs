@@ -0,0 +1,395 @@
+//! An in-memory `FileSystem`, mostly for tests.
+//!
+//! Trees can be built up entry by entry, from a list of paths, or from a
+//! small indentation-based DSL:
+//!
+//! ```ignore
+//! let fs = MemoryFs::from_tree("
+//!   src/
+//!     lib.rs
+//!     bin/
+//!       main.rs
+//!   latest -> src/bin
+//! ");
+//! ```
+//!
+//! In both forms a trailing `/` makes a directory and `link -> target` a
+//! symlink, with relative targets resolved from the link's directory.
+//! Relative paths are looked up from the root, which is also its own
+//! parent.
+
+use std::collections::BTreeMap;
+use std::io::{IoError, IoResult};
+use std::io;
+use std::path::BytesContainer;
+
+use entry::{FileKind, Metadata};
+use filesystem::{FileSystem, DirEntries};
+
+// symlinks followed before giving up, as with ELOOP
+static MAX_LINKS: usize = 40;
+
+enum Kind {
+  File,
+  Directory(BTreeMap<Vec<u8>, usize>),
+  Symlink(Path),
+}
+
+struct Node {
+  kind: Kind,
+  parent: usize,
+  metadata: Metadata,
+}
+
+pub struct MemoryFs {
+  // the root is always the first node
+  nodes: Vec<Node>,
+}
+
+fn not_found(path: &Path) -> IoError {
+  IoError {
+    kind: io::FileNotFound,
+    desc: "no such file or directory",
+    detail: Some(path.display().to_string()),
+  }
+}
+
+fn not_a_directory(path: &Path) -> IoError {
+  IoError {
+    kind: io::MismatchedFileTypeForOperation,
+    desc: "not a directory",
+    detail: Some(path.display().to_string()),
+  }
+}
+
+impl MemoryFs {
+  pub fn new() -> MemoryFs {
+    MemoryFs {
+      nodes: vec![MemoryFs::node(Kind::Directory(BTreeMap::new()), 0)],
+    }
+  }
+
+  /// Builds a tree from a list of paths; see the module documentation.
+  pub fn from_paths<'a, I: Iterator<Item=&'a str>>(paths: I) -> MemoryFs {
+    let mut fs = MemoryFs::new();
+
+    for path in paths {
+      fs.add(path);
+    }
+
+    fs
+  }
+
+  /// Builds a tree from the indentation-based DSL; see the module
+  /// documentation. Blank lines are ignored.
+  pub fn from_tree(tree: &str) -> MemoryFs {
+    let mut fs = MemoryFs::new();
+    // (indentation, directory) of each enclosing directory
+    let mut parents: Vec<(usize, String)> = Vec::new();
+
+    for line in tree.lines() {
+      let entry = line.trim_left();
+
+      if entry.trim_right().is_empty() {
+        continue;
+      }
+
+      let indent = line.len() - entry.len();
+
+      while parents.last().map_or(false, |&(i, _)| i >= indent) {
+        parents.pop();
+      }
+
+      let path = match parents.last() {
+        Some(&(_, ref dir)) => format!("{}{}", dir, entry.trim_right()),
+        None => entry.trim_right().to_string(),
+      };
+
+      fs.add(path.as_slice());
+
+      if path.ends_with("/") {
+        parents.push((indent, path));
+      }
+    }
+
+    fs
+  }
+
+  fn add(&mut self, path: &str) {
+    match path.find_str(" -> ") {
+      Some(i) => { self.add_symlink(path.slice_to(i), path.slice_from(i + 4)); }
+      None if path.ends_with("/") => { self.add_dir(path); }
+      None => { self.add_file(path); }
+    }
+  }
+
+  pub fn add_file<P: BytesContainer>(&mut self, path: P) -> &mut MemoryFs {
+    self.insert(&Path::new(path), Kind::File);
+    self
+  }
+
+  pub fn add_dir<P: BytesContainer>(&mut self, path: P) -> &mut MemoryFs {
+    self.insert(&Path::new(path), Kind::Directory(BTreeMap::new()));
+    self
+  }
+
+  pub fn add_symlink<P: BytesContainer, T: BytesContainer>(&mut self, path: P, target: T)
+                                                           -> &mut MemoryFs {
+    self.insert(&Path::new(path), Kind::Symlink(Path::new(target)));
+    self
+  }
+
+  /// Changes the metadata reported for an existing entry. Its kind can't
+  /// be changed this way.
+  pub fn set_metadata<P, F>(&mut self, path: P, f: F) -> &mut MemoryFs
+    where P: BytesContainer, F: FnOnce(&mut Metadata) {
+    let index = self.find(&Path::new(path), false)
+      .ok().expect("no such entry in the in-memory filesystem");
+
+    f(&mut self.nodes[index].metadata);
+    self
+  }
+
+  fn node(kind: Kind, parent: usize) -> Node {
+    let (file_kind, permissions) = match kind {
+      Kind::File => (FileKind::File, 0o644),
+      Kind::Directory(..) => (FileKind::Directory, 0o755),
+      Kind::Symlink(..) => (FileKind::Symlink, 0o777),
+    };
+
+    Node {
+      kind: kind,
+      parent: parent,
+      metadata: Metadata {
+        kind: file_kind,
+        size: 0,
+        modified: 0,
+        permissions: permissions,
+        uid: 0,
+        gid: 0,
+      },
+    }
+  }
+
+  // creates `path` and any missing parent directories, replacing whatever
+  // was at `path` already unless both are directories
+  fn insert(&mut self, path: &Path, kind: Kind) -> usize {
+    let mut current = 0;
+    let components: Vec<&[u8]> = path.components().collect();
+
+    for (i, name) in components.iter().enumerate() {
+      let last = i + 1 == components.len();
+
+      if *name == b"." {
+        continue;
+      }
+
+      if *name == b".." {
+        current = self.nodes[current].parent;
+        continue;
+      }
+
+      let existing = match self.nodes[current].kind {
+        Kind::Directory(ref children) => children.get(*name).map(|i| *i),
+        _ => panic!("{} is not a directory", path.display()),
+      };
+
+      if !last {
+        current = match existing {
+          Some(index) => index,
+          None => self.replace(current, *name, Kind::Directory(BTreeMap::new())),
+        };
+        continue;
+      }
+
+      if let Some(index) = existing {
+        let both_dirs = match (&self.nodes[index].kind, &kind) {
+          (&Kind::Directory(..), &Kind::Directory(..)) => true,
+          _ => false,
+        };
+
+        if both_dirs {
+          return index;
+        }
+      }
+
+      return self.replace(current, *name, kind);
+    }
+
+    current
+  }
+
+  fn replace(&mut self, parent: usize, name: &[u8], kind: Kind) -> usize {
+    let index = self.nodes.len();
+    self.nodes.push(MemoryFs::node(kind, parent));
+
+    if let Kind::Directory(ref mut children) = self.nodes[parent].kind {
+      children.insert(name.to_vec(), index);
+    }
+
+    index
+  }
+
+  // resolves `path` to a node, following symlinks in every component but
+  // the last, and in the last one too if `follow` is set
+  fn find(&self, path: &Path, follow: bool) -> IoResult<usize> {
+    let mut links = 0;
+    self.resolve(0, path, follow, &mut links)
+  }
+
+  fn resolve(&self, start: usize, path: &Path, follow: bool, links: &mut usize)
+             -> IoResult<usize> {
+    let mut current = if path.is_absolute() { 0 } else { start };
+    let components: Vec<&[u8]> = path.components().collect();
+
+    for (i, name) in components.iter().enumerate() {
+      let last = i + 1 == components.len();
+
+      if *name == b"." {
+        continue;
+      }
+
+      if *name == b".." {
+        current = self.nodes[current].parent;
+        continue;
+      }
+
+      let child = match self.nodes[current].kind {
+        Kind::Directory(ref children) => match children.get(*name) {
+          Some(child) => *child,
+          None => return Err(not_found(path)),
+        },
+        _ => return Err(not_a_directory(path)),
+      };
+
+      current = child;
+
+      if let Kind::Symlink(ref target) = self.nodes[current].kind {
+        if last && !follow {
+          break;
+        }
+
+        *links += 1;
+
+        if *links > MAX_LINKS {
+          return Err(IoError {
+            kind: io::OtherIoError,
+            desc: "too many levels of symbolic links",
+            detail: Some(path.display().to_string()),
+          });
+        }
+
+        let parent = self.nodes[current].parent;
+        current = try!(self.resolve(parent, target, true, links));
+      }
+    }
+
+    Ok(current)
+  }
+}
+
+impl FileSystem for MemoryFs {
+  fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+    let index = try!(self.find(path, true));
+
+    match self.nodes[index].kind {
+      Kind::Directory(ref children) => {
+        let entries: Vec<(Path, Option<FileKind>)> = children.iter()
+          .map(|(name, &child)| {
+            (path.join(name.as_slice()), Some(self.nodes[child].metadata.kind))
+          })
+          .collect();
+
+        Ok(Box::new(entries.into_iter()) as DirEntries)
+      }
+      _ => Err(not_a_directory(path)),
+    }
+  }
+
+  fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+    self.find(path, false).map(|index| self.nodes[index].metadata.kind)
+  }
+
+  fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+    self.find(path, true).map(|index| self.nodes[index].metadata)
+  }
+
+  fn read_link(&self, path: &Path) -> IoResult<Path> {
+    let index = try!(self.find(path, false));
+
+    match self.nodes[index].kind {
+      Kind::Symlink(ref target) => Ok(target.clone()),
+      _ => Err(IoError {
+        kind: io::InvalidInput,
+        desc: "not a symbolic link",
+        detail: Some(path.display().to_string()),
+      }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::MemoryFs;
+  use entry::FileKind;
+  use filesystem::FileSystem;
+
+  fn names(fs: &MemoryFs, path: &str) -> Vec<String> {
+    fs.read_dir(&Path::new(path)).unwrap()
+      .map(|(p, _)| p.filename_str().unwrap().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn from_paths() {
+    let fs = MemoryFs::from_paths(["a/b/c.txt", "a/d/", "e -> a/b", "f"].iter().map(|p| *p));
+
+    assert_eq!(names(&fs, "."), vec!["a", "e", "f"]);
+    assert_eq!(names(&fs, "a"), vec!["b", "d"]);
+    assert_eq!(names(&fs, "/a/b"), vec!["c.txt"]);
+    assert_eq!(names(&fs, "e"), vec!["c.txt"]);
+
+    let kind = |&: p: &str| fs.file_kind(&Path::new(p)).ok();
+    assert_eq!(kind("a"), Some(FileKind::Directory));
+    assert_eq!(kind("a/d"), Some(FileKind::Directory));
+    assert_eq!(kind("a/b/c.txt"), Some(FileKind::File));
+    assert_eq!(kind("e"), Some(FileKind::Symlink));
+    assert_eq!(kind("e/c.txt"), Some(FileKind::File));
+    assert_eq!(kind("nope"), None);
+
+    assert_eq!(fs.metadata(&Path::new("e")).unwrap().kind, FileKind::Directory);
+    assert_eq!(fs.read_link(&Path::new("e")).unwrap(), Path::new("a/b"));
+    assert!(fs.read_dir(&Path::new("f")).is_err());
+  }
+
+  #[test]
+  fn from_tree() {
+    let fs = MemoryFs::from_tree("
+      src/
+        lib.rs
+        bin/
+          main.rs
+      latest -> src/bin
+      readme.md
+    ");
+
+    assert_eq!(names(&fs, "."), vec!["latest", "readme.md", "src"]);
+    assert_eq!(names(&fs, "src"), vec!["bin", "lib.rs"]);
+    assert_eq!(names(&fs, "latest"), vec!["main.rs"]);
+  }
+
+  #[test]
+  fn symlink_loops() {
+    let fs = MemoryFs::from_paths(["a -> b", "b -> a"].iter().map(|p| *p));
+    assert!(fs.metadata(&Path::new("a")).is_err());
+    assert_eq!(fs.file_kind(&Path::new("a")).unwrap(), FileKind::Symlink);
+  }
+
+  #[test]
+  fn metadata() {
+    let mut fs = MemoryFs::new();
+    fs.add_file("big").set_metadata("big", |m| m.size = 1 << 20);
+
+    assert_eq!(fs.metadata(&Path::new("big")).unwrap().size, 1 << 20);
+  }
+}
@@ -0,0 +1,398 @@
+//! Options controlling which entries `glob_with` yields.
+
+use std::cmp::Ordering;
+
+use entry::{GlobEntry, FileKind};
+use filesystem::FileSystem;
+use pattern::PathStyle;
+
+/// A set of file kinds.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub struct FileTypes {
+  bits: u8,
+}
+
+impl FileTypes {
+  fn bit(kind: FileKind) -> u8 {
+    match kind {
+      FileKind::File => 1 << 0,
+      FileKind::Directory => 1 << 1,
+      FileKind::Symlink => 1 << 2,
+      FileKind::Fifo => 1 << 3,
+      FileKind::Socket => 1 << 4,
+      FileKind::BlockDevice => 1 << 5,
+      FileKind::CharDevice => 1 << 6,
+      FileKind::Other => 1 << 7,
+    }
+  }
+
+  pub fn empty() -> FileTypes {
+    FileTypes { bits: 0 }
+  }
+
+  pub fn all() -> FileTypes {
+    FileTypes { bits: !0 }
+  }
+
+  pub fn of(kind: FileKind) -> FileTypes {
+    FileTypes { bits: FileTypes::bit(kind) }
+  }
+
+  pub fn files() -> FileTypes {
+    FileTypes::of(FileKind::File)
+  }
+
+  pub fn directories() -> FileTypes {
+    FileTypes::of(FileKind::Directory)
+  }
+
+  pub fn symlinks() -> FileTypes {
+    FileTypes::of(FileKind::Symlink)
+  }
+
+  /// FIFOs, sockets, device nodes and anything else that isn't a file,
+  /// directory or symlink.
+  pub fn special() -> FileTypes {
+    FileTypes::of(FileKind::Fifo)
+      .union(FileTypes::of(FileKind::Socket))
+      .union(FileTypes::of(FileKind::BlockDevice))
+      .union(FileTypes::of(FileKind::CharDevice))
+      .union(FileTypes::of(FileKind::Other))
+  }
+
+  pub fn union(self, other: FileTypes) -> FileTypes {
+    FileTypes { bits: self.bits | other.bits }
+  }
+
+  pub fn intersection(self, other: FileTypes) -> FileTypes {
+    FileTypes { bits: self.bits & other.bits }
+  }
+
+  pub fn difference(self, other: FileTypes) -> FileTypes {
+    FileTypes { bits: self.bits & !other.bits }
+  }
+
+  pub fn contains(&self, kind: FileKind) -> bool {
+    self.bits & FileTypes::bit(kind) != 0
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum SortKey {
+  Name,
+  Size,
+  /// most recently modified first
+  Modified,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub struct Sort {
+  pub key: SortKey,
+  pub reverse: bool,
+}
+
+/// Options for `glob_with`.
+///
+/// File types are those of the entries themselves: a symlink to a file is
+/// a `Symlink`, not a `File`, the same as `find -type` and zsh qualifiers.
+///
+/// The metadata predicates follow symlinks, and an entry whose metadata
+/// can't be read never satisfies them. Metadata is only fetched when at
+/// least one predicate is set, and at most once per entry.
+#[derive(Clone, Show)]
+pub struct GlobOptions {
+  pub file_types: FileTypes,
+
+  /// size in bytes, exclusive
+  pub larger_than: Option<u64>,
+  pub smaller_than: Option<u64>,
+
+  /// milliseconds since the epoch; an entry modified at exactly this time
+  /// is accepted
+  pub modified_since: Option<u64>,
+  /// milliseconds since the epoch; an entry modified at exactly this time
+  /// is rejected
+  pub modified_before: Option<u64>,
+
+  /// permission bits which must all be set
+  pub mode: Option<u32>,
+  /// any of the execute bits is set
+  pub executable: bool,
+
+  pub uid: Option<u64>,
+  pub gid: Option<u64>,
+
+  /// Setting either of these makes the walker collect every match before
+  /// yielding the first one.
+  pub sort: Option<Sort>,
+  /// 1-based inclusive bounds, negative ones counting from the end
+  pub slice: Option<(isize, isize)>,
+
+  /// How the pattern and the paths it's matched against are read; the
+  /// host's style by default.
+  pub style: PathStyle,
+}
+
+impl GlobOptions {
+  pub fn new() -> GlobOptions {
+    GlobOptions {
+      file_types: FileTypes::all(),
+      larger_than: None,
+      smaller_than: None,
+      modified_since: None,
+      modified_before: None,
+      mode: None,
+      executable: false,
+      uid: None,
+      gid: None,
+      sort: None,
+      slice: None,
+      style: PathStyle::host(),
+    }
+  }
+
+  pub fn files_only() -> GlobOptions {
+    GlobOptions::new().file_types(FileTypes::files())
+  }
+
+  pub fn dirs_only() -> GlobOptions {
+    GlobOptions::new().file_types(FileTypes::directories())
+  }
+
+  pub fn file_types(mut self, file_types: FileTypes) -> GlobOptions {
+    self.file_types = file_types;
+    self
+  }
+
+  pub fn larger_than(mut self, size: u64) -> GlobOptions {
+    self.larger_than = Some(size);
+    self
+  }
+
+  pub fn smaller_than(mut self, size: u64) -> GlobOptions {
+    self.smaller_than = Some(size);
+    self
+  }
+
+  /// Only entries modified at or after `ms`.
+  pub fn modified_since(mut self, ms: u64) -> GlobOptions {
+    self.modified_since = Some(ms);
+    self
+  }
+
+  /// Only entries modified strictly before `ms`.
+  pub fn modified_before(mut self, ms: u64) -> GlobOptions {
+    self.modified_before = Some(ms);
+    self
+  }
+
+  pub fn mode(mut self, bits: u32) -> GlobOptions {
+    self.mode = Some(bits);
+    self
+  }
+
+  pub fn executable(mut self) -> GlobOptions {
+    self.executable = true;
+    self
+  }
+
+  pub fn uid(mut self, uid: u64) -> GlobOptions {
+    self.uid = Some(uid);
+    self
+  }
+
+  pub fn gid(mut self, gid: u64) -> GlobOptions {
+    self.gid = Some(gid);
+    self
+  }
+
+  pub fn sort_by(mut self, key: SortKey, reverse: bool) -> GlobOptions {
+    self.sort = Some(Sort { key: key, reverse: reverse });
+    self
+  }
+
+  pub fn slice(mut self, start: isize, end: isize) -> GlobOptions {
+    self.slice = Some((start, end));
+    self
+  }
+
+  pub fn path_style(mut self, style: PathStyle) -> GlobOptions {
+    self.style = style;
+    self
+  }
+
+  pub fn is_buffered(&self) -> bool {
+    self.sort.is_some() || self.slice.is_some()
+  }
+
+  /// Sorts and slices a complete list of matches, found in `fs`.
+  pub fn arrange_in<F: FileSystem>(&self, fs: &F, mut entries: Vec<GlobEntry>)
+                                   -> Vec<GlobEntry> {
+    if let Some(sort) = self.sort {
+      entries.sort_by(|a, b| {
+        let ordering = compare(fs, sort.key, a, b);
+        if sort.reverse { ordering.reverse() } else { ordering }
+      });
+    }
+
+    match self.slice {
+      None => entries,
+      Some((start, end)) => {
+        let len = entries.len() as isize;
+        let resolve = |&: i: isize| if i < 0 { len + i } else { i - 1 };
+
+        let start = resolve(start);
+        let end = resolve(end);
+
+        if start > end || end < 0 || start >= len {
+          return Vec::new();
+        }
+
+        let start = if start < 0 { 0 } else { start as usize };
+        let end = if end >= len { len as usize } else { end as usize + 1 };
+
+        entries.into_iter().skip(start).take(end - start).collect()
+      }
+    }
+  }
+
+  fn has_predicates(&self) -> bool {
+    self.larger_than.is_some() || self.smaller_than.is_some() ||
+      self.modified_since.is_some() || self.modified_before.is_some() ||
+      self.mode.is_some() || self.executable ||
+      self.uid.is_some() || self.gid.is_some()
+  }
+
+  /// Whether the walker should yield `entry`, found in `fs`.
+  pub fn accepts_in<F: FileSystem>(&self, fs: &F, entry: &GlobEntry) -> bool {
+    if self.file_types != FileTypes::all() {
+      let kind = entry.file_kind_in(fs);
+
+      if !kind.map_or(false, |kind| self.file_types.contains(kind)) {
+        return false;
+      }
+    }
+
+    if !self.has_predicates() {
+      return true;
+    }
+
+    let md = match entry.metadata_in(fs) {
+      Ok(md) => md,
+      Err(..) => return false,
+    };
+
+    self.larger_than.map_or(true, |size| md.size > size) &&
+      self.smaller_than.map_or(true, |size| md.size < size) &&
+      self.modified_since.map_or(true, |ms| md.modified >= ms) &&
+      self.modified_before.map_or(true, |ms| md.modified < ms) &&
+      self.mode.map_or(true, |bits| md.permissions & bits == bits) &&
+      (!self.executable || md.permissions & 0o111 != 0) &&
+      self.uid.map_or(true, |uid| md.uid == uid) &&
+      self.gid.map_or(true, |gid| md.gid == gid)
+  }
+}
+
+fn compare<F: FileSystem>(fs: &F, key: SortKey, a: &GlobEntry, b: &GlobEntry) -> Ordering {
+  match key {
+    SortKey::Name => a.path().cmp(b.path()),
+    SortKey::Size => {
+      let size = |&: e: &GlobEntry| e.metadata_in(fs).map(|m| m.size).unwrap_or(0);
+      size(a).cmp(&size(b))
+    }
+    SortKey::Modified => {
+      let modified = |&: e: &GlobEntry| e.metadata_in(fs).map(|m| m.modified).unwrap_or(0);
+      modified(b).cmp(&modified(a))
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::{self, TempDir};
+  use std::io::fs;
+
+  use super::{FileTypes, GlobOptions, SortKey};
+  use entry::{GlobEntry, FileKind};
+  use filesystem::OsFs;
+
+  #[test]
+  fn file_types() {
+    let special = FileTypes::special();
+    assert!(special.contains(FileKind::Fifo));
+    assert!(special.contains(FileKind::Socket));
+    assert!(!special.contains(FileKind::File));
+    assert!(!special.contains(FileKind::Symlink));
+
+    let no_special = FileTypes::all().difference(special);
+    assert!(no_special.contains(FileKind::File));
+    assert!(no_special.contains(FileKind::Directory));
+    assert!(!no_special.contains(FileKind::BlockDevice));
+  }
+
+  #[test]
+  fn accepts_known_kind() {
+    // these don't exist, so the kind must come from the entry itself
+    let file =
+      GlobEntry::with_kind(Path::new("/does/not/exist"), 0, Some(FileKind::File));
+    let link =
+      GlobEntry::with_kind(Path::new("/does/not/exist"), 0, Some(FileKind::Symlink));
+
+    assert!(GlobOptions::files_only().accepts_in(&OsFs, &file));
+    assert!(!GlobOptions::files_only().accepts_in(&OsFs, &link));
+    assert!(!GlobOptions::dirs_only().accepts_in(&OsFs, &file));
+    assert!(GlobOptions::new().file_types(FileTypes::symlinks()).accepts_in(&OsFs, &link));
+  }
+
+  #[test]
+  fn predicates() {
+    let root = TempDir::new("glob-options").unwrap();
+    let path = root.path().join("file");
+    io::File::create(&path).write(&[0u8; 100]).unwrap();
+    fs::chmod(&path, io::USER_READ | io::USER_WRITE).unwrap();
+
+    let entry = GlobEntry::new(path.clone(), 1);
+    assert!(GlobOptions::new().larger_than(10).accepts_in(&OsFs, &entry));
+    assert!(!GlobOptions::new().larger_than(100).accepts_in(&OsFs, &entry));
+    assert!(GlobOptions::new().smaller_than(101).accepts_in(&OsFs, &entry));
+    assert!(!GlobOptions::new().larger_than(10).smaller_than(50).accepts_in(&OsFs, &entry));
+    assert!(GlobOptions::new().modified_since(0).accepts_in(&OsFs, &entry));
+    assert!(GlobOptions::new().mode(0o600).accepts_in(&OsFs, &entry));
+    assert!(!GlobOptions::new().mode(0o640).accepts_in(&OsFs, &entry));
+    assert!(!GlobOptions::new().executable().accepts_in(&OsFs, &entry));
+
+    fs::chmod(&path, io::USER_RWX).unwrap();
+    let entry = GlobEntry::new(path, 1);
+    assert!(GlobOptions::new().executable().accepts_in(&OsFs, &entry));
+
+    // predicates never hold for entries that can't be stat'ed
+    let missing = GlobEntry::new(root.path().join("missing"), 1);
+    assert!(!GlobOptions::new().smaller_than(1000).accepts_in(&OsFs, &missing));
+    assert!(GlobOptions::new().accepts_in(&OsFs, &missing));
+  }
+
+  #[test]
+  fn arrange() {
+    fn names(options: &GlobOptions) -> Vec<String> {
+      let entries = ["c", "a", "e", "b", "d"].iter()
+        .map(|n| GlobEntry::new(Path::new(*n), 1))
+        .collect();
+
+      options.arrange_in(&OsFs, entries).into_iter()
+        .map(|e| e.path().as_str().unwrap().to_string())
+        .collect()
+    }
+
+    let sorted = GlobOptions::new().sort_by(SortKey::Name, false);
+    assert_eq!(names(&sorted), vec!["a", "b", "c", "d", "e"]);
+    assert_eq!(names(&sorted.clone().slice(1, 3)), vec!["a", "b", "c"]);
+    assert_eq!(names(&sorted.clone().slice(2, 2)), vec!["b"]);
+    assert_eq!(names(&sorted.clone().slice(-2, -1)), vec!["d", "e"]);
+    assert_eq!(names(&sorted.clone().slice(4, 10)), vec!["d", "e"]);
+    assert!(names(&sorted.clone().slice(6, 10)).is_empty());
+    assert!(names(&sorted.clone().slice(3, 1)).is_empty());
+
+    let reversed = GlobOptions::new().sort_by(SortKey::Name, true).slice(1, 1);
+    assert_eq!(names(&reversed), vec!["e"]);
+  }
+}
@@ -0,0 +1,413 @@
+//! A multi-threaded glob walker.
+//!
+//! Directory reads are spread across a pool of threads. Each thread works
+//! through its own queue of pending directories, newest first so memory
+//! stays bounded by depth, and steals the oldest work from the others when
+//! it runs dry. Matches are streamed back through a bounded channel, so the
+//! iterator yields them as soon as they're found, in no particular order,
+//! and the workers block once `BUFFER` of them are waiting.
+//!
+//! The selection logic is that of `glob_with`: the same pattern and options
+//! yield the same set of entries.
+
+use std::collections::RingBuf;
+use std::os;
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, Receiver};
+use std::thread::Thread;
+use std::vec;
+
+use entry::GlobEntry;
+use filesystem::{FileSystem, OsFs};
+use options::GlobOptions;
+use pattern::Error;
+use plan::{self, Plan, Component, Filter};
+
+// matches held for the iterator before the workers wait on it
+static BUFFER: usize = 256;
+
+// apply `components[index]` to `entry`
+struct Task {
+  entry: GlobEntry,
+  index: usize,
+}
+
+struct Shared<F> {
+  fs: F,
+  components: Vec<Component>,
+  filter: Filter,
+  queues: Vec<Mutex<RingBuf<Task>>>,
+
+  // tasks queued or being processed; the walk is over when this hits zero
+  pending: AtomicUsize,
+  // set once the receiving end has gone away
+  cancelled: AtomicBool,
+
+  // idle workers wait on `wake` holding `idle`, which is also taken to
+  // notify them so no wakeup is lost between their check and the wait
+  idle: Mutex<()>,
+  wake: Condvar,
+}
+
+impl<F: FileSystem> Shared<F> {
+  fn push(&self, worker: usize, task: Task) {
+    self.pending.fetch_add(1, Ordering::SeqCst);
+    self.queues[worker].lock().unwrap().push_back(task);
+
+    let _idle = self.idle.lock().unwrap();
+    self.wake.notify_one();
+  }
+
+  // ends the walk early; workers stop before their next directory
+  fn cancel(&self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+
+    let _idle = self.idle.lock().unwrap();
+    self.wake.notify_all();
+  }
+
+  fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+
+  fn has_work(&self) -> bool {
+    self.queues.iter().any(|queue| !queue.lock().unwrap().is_empty())
+  }
+
+  fn pop(&self, worker: usize) -> Option<Task> {
+    if let Some(task) = self.queues[worker].lock().unwrap().pop_back() {
+      return Some(task);
+    }
+
+    let count = self.queues.len();
+
+    for i in range(1, count) {
+      let victim = (worker + i) % count;
+
+      if let Some(task) = self.queues[victim].lock().unwrap().pop_front() {
+        return Some(task);
+      }
+    }
+
+    None
+  }
+
+  fn emit(&self, entry: GlobEntry, tx: &SyncSender<GlobEntry>) {
+    if !self.filter.accepts(&self.fs, &entry) {
+      return;
+    }
+
+    if tx.send(entry).is_err() {
+      self.cancel();
+    }
+  }
+
+  fn process(&self, worker: usize, task: Task, tx: &SyncSender<GlobEntry>) {
+    let Task { entry, index } = task;
+
+    if index == self.components.len() {
+      return self.emit(entry, tx);
+    }
+
+    if !entry.is_dir_in(&self.fs) {
+      return;
+    }
+
+    let depth = entry.depth() + 1;
+
+    match self.components[index] {
+      Component::Precise(ref name) => {
+        let style = self.filter.options.style;
+
+        if let Some(joined) = plan::resolve(&self.fs, &entry, name.as_slice(), style) {
+          self.process(worker, Task { entry: joined, index: index + 1 }, tx);
+        }
+      }
+
+      Component::Wildcard(ref pattern) => {
+        if self.is_cancelled() {
+          return;
+        }
+
+        let ents = match self.fs.read_dir(entry.path()) {
+          Ok(ents) => ents,
+          Err(..) => return,
+        };
+
+        for (path, kind) in ents {
+          if !pattern.matches_path(&path) {
+            continue;
+          }
+
+          let child = GlobEntry::with_kind(path, depth, kind);
+
+          if index + 1 == self.components.len() {
+            self.emit(child, tx);
+          } else {
+            self.push(worker, Task { entry: child, index: index + 1 });
+          }
+        }
+      }
+
+      Component::Recursive => {
+        if self.is_cancelled() {
+          return;
+        }
+
+        if let Ok(ents) = self.fs.read_dir(entry.path()) {
+          for (path, kind) in ents {
+            let child = GlobEntry::with_kind(path, depth, kind);
+
+            if child.is_dir_in(&self.fs) {
+              self.push(worker, Task { entry: child, index: index });
+            }
+          }
+        }
+
+        // `**` also matches no components at all
+        self.process(worker, Task { entry: entry, index: index + 1 }, tx);
+      }
+    }
+  }
+
+  fn run(&self, worker: usize, tx: SyncSender<GlobEntry>) {
+    while !self.is_cancelled() {
+      match self.pop(worker) {
+        Some(task) => {
+          self.process(worker, task, &tx);
+
+          // the last task done ends the walk for everyone
+          if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _idle = self.idle.lock().unwrap();
+            self.wake.notify_all();
+          }
+        }
+        None => {
+          let idle = self.idle.lock().unwrap();
+
+          if self.pending.load(Ordering::SeqCst) == 0 {
+            break;
+          }
+
+          if !self.is_cancelled() && !self.has_work() {
+            let _idle = self.wake.wait(idle).unwrap();
+          }
+        }
+      }
+    }
+  }
+}
+
+/// The results of `glob_parallel`.
+///
+/// Dropping it stops the worker threads before their next directory, and
+/// wakes any waiting on a full buffer.
+pub struct ParallelPaths<F: FileSystem = OsFs> {
+  rx: Receiver<GlobEntry>,
+  options: GlobOptions,
+  shared: Arc<Shared<F>>,
+
+  // every match, sorted and sliced, when the options call for it
+  buffered: Option<vec::IntoIter<GlobEntry>>,
+}
+
+impl<F: FileSystem> Iterator for ParallelPaths<F> {
+  type Item = GlobEntry;
+
+  fn next(&mut self) -> Option<GlobEntry> {
+    if !self.options.is_buffered() {
+      return self.rx.recv().ok();
+    }
+
+    if self.buffered.is_none() {
+      let entries = self.rx.iter().collect();
+      self.buffered = Some(self.options.arrange_in(&self.shared.fs, entries).into_iter());
+    }
+
+    self.buffered.as_mut().unwrap().next()
+  }
+}
+
+impl<F: FileSystem> Drop for ParallelPaths<F> {
+  fn drop(&mut self) {
+    self.shared.cancel();
+  }
+}
+
+/// Like `glob_with`, but walks directories on `threads` threads.
+///
+/// Zero threads means one per CPU.
+pub fn glob_parallel(pattern: &str, options: &GlobOptions, threads: usize)
+                     -> Result<ParallelPaths, Error> {
+  glob_parallel_in(OsFs, pattern, options, threads)
+}
+
+/// Like `glob_parallel`, in `fs`, which the workers share; hence the `Send`
+/// and `Sync` bounds.
+pub fn glob_parallel_in<F>(fs: F, pattern: &str, options: &GlobOptions, threads: usize)
+                           -> Result<ParallelPaths<F>, Error>
+  where F: FileSystem + Send + Sync + 'static {
+  let plan = try!(Plan::new(pattern, options));
+  let threads = if threads == 0 { os::num_cpus() } else { threads };
+  let options = plan.filter.options.clone();
+
+  let shared = Arc::new(Shared {
+    fs: fs,
+    components: plan.components,
+    filter: plan.filter,
+    queues: range(0, threads).map(|_| Mutex::new(RingBuf::new())).collect(),
+    pending: AtomicUsize::new(0),
+    cancelled: AtomicBool::new(false),
+    idle: Mutex::new(()),
+    wake: Condvar::new(),
+  });
+
+  shared.push(0, Task { entry: GlobEntry::new(plan.scope, 0), index: 0 });
+
+  let (tx, rx) = sync_channel(BUFFER);
+
+  for worker in range(0, threads) {
+    let shared = shared.clone();
+    let tx = tx.clone();
+
+    Thread::spawn(move || shared.run(worker, tx));
+  }
+
+  Ok(ParallelPaths {
+    rx: rx,
+    options: options,
+    shared: shared,
+    buffered: None,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashSet;
+  use std::io::{IoResult, TempDir};
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::mpsc::{channel, Sender};
+
+  use super::{glob_parallel, glob_parallel_in, BUFFER};
+  use entry::{FileKind, Metadata};
+  use filesystem::{FileSystem, DirEntries};
+  use glob::glob_with;
+  use memfs::MemoryFs;
+  use options::GlobOptions;
+  use testing;
+
+  fn tree() -> TempDir {
+    testing::tree("glob-parallel", &["a", "a/b", "a/b/c", "d", "d/e"],
+                  &["x.rs", "a/y.rs", "a/b/z.rs", "a/b/c/w.rs", "d/e/v.txt"])
+  }
+
+  fn same_as_serial(root: &TempDir, pattern: &str, options: &GlobOptions) {
+    let pattern = root.path().join(pattern);
+    let pattern = pattern.as_str().unwrap();
+
+    let serial: HashSet<Path> =
+      glob_with(pattern, options).unwrap().map(|e| e.into_path()).collect();
+
+    for &threads in [1, 4].iter() {
+      let parallel: HashSet<Path> =
+        glob_parallel(pattern, options, threads).unwrap().map(|e| e.into_path()).collect();
+
+      assert_eq!(parallel, serial);
+    }
+  }
+
+  #[test]
+  fn matches_serial() {
+    let root = tree();
+
+    same_as_serial(&root, "**/*.rs", &GlobOptions::new());
+    same_as_serial(&root, "**", &GlobOptions::new());
+    same_as_serial(&root, "*/b/*", &GlobOptions::new());
+    same_as_serial(&root, "a/**/c", &GlobOptions::new());
+    same_as_serial(&root, "*/", &GlobOptions::new());
+    same_as_serial(&root, "**/*", &GlobOptions::files_only());
+    same_as_serial(&root, "**/*", &GlobOptions::dirs_only());
+  }
+
+  #[test]
+  fn sorted() {
+    let root = tree();
+    let pattern = root.path().join("**/*.rs(on)");
+
+    let paths: Vec<Path> =
+      glob_parallel(pattern.as_str().unwrap(), &GlobOptions::new(), 4).unwrap()
+        .map(|e| e.into_path())
+        .collect();
+
+    let mut expected = paths.clone();
+    expected.sort();
+
+    assert_eq!(paths.len(), 4);
+    assert_eq!(paths, expected);
+  }
+
+  // an in-memory tree counting its listings, which reports on `done` when
+  // the workers let go of it
+  struct Counted {
+    fs: MemoryFs,
+    reads: Arc<AtomicUsize>,
+    done: Sender<()>,
+  }
+
+  impl FileSystem for Counted {
+    fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+      self.reads.fetch_add(1, Ordering::SeqCst);
+      self.fs.read_dir(path)
+    }
+
+    fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+      self.fs.file_kind(path)
+    }
+
+    fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+      self.fs.metadata(path)
+    }
+
+    fn read_link(&self, path: &Path) -> IoResult<Path> {
+      self.fs.read_link(path)
+    }
+  }
+
+  impl Drop for Counted {
+    fn drop(&mut self) {
+      let _ = self.done.send(());
+    }
+  }
+
+  #[test]
+  fn early_drop() {
+    let mut fs = MemoryFs::new();
+
+    for a in range(0, 10) {
+      for b in range(0, 10) {
+        for c in range(0, 10) {
+          fs.add_dir(format!("{}/{}/{}/", a, b, c).as_slice());
+        }
+      }
+    }
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let (done, finished) = channel();
+    let counted = Counted { fs: fs, reads: reads.clone(), done: done };
+
+    {
+      let mut paths = glob_parallel_in(counted, "**", &GlobOptions::new(), 4).unwrap();
+      assert!(paths.next().is_some());
+    }
+
+    // every worker has stopped once the tree is dropped
+    finished.recv().unwrap();
+
+    // each listing is followed by a match, so with the buffer full and one
+    // match taken, a worker per thread gets at most one listing further
+    let reads = reads.load(Ordering::SeqCst);
+    assert!(reads <= BUFFER + 6, "walked {} of 1111 directories", reads);
+  }
+}
@@ -1,6 +1,13 @@
 use regex::Regex;
+use std::ascii::AsciiExt;
+use std::char;
+use std::collections::{BTreeSet, HashSet, RingBuf};
+use std::error;
 use std::fmt;
-use std::path;
+use std::ops::Range;
+use std::str;
+
+use ast::Ast;
 
 use self::Token::{
   Char,
@@ -12,44 +19,821 @@ use self::Token::{
 };
 use self::CharSpecifier::{SingleChar, CharRange};
 
+/// A piece of a parsed pattern.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
-enum Token {
+pub enum Token {
+  /// a literal character
   Char(char),
+  /// `?`
   AnyChar,
+  /// `*`
   AnySequence,
+  /// `**`, along with the separator after it
   AnyRecursiveSequence,
+  /// `[...]`
   AnyWithin(Vec<CharSpecifier>),
+  /// `[!...]`
   AnyExcept(Vec<CharSpecifier>)
 }
 
+/// A member of a character class.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
-enum CharSpecifier {
+pub enum CharSpecifier {
   SingleChar(char),
   CharRange(char, char)
 }
 
+/// How patterns, and the paths matched against them, are read.
+///
+/// Paths are put into a canonical form before matching, with `/` as the
+/// only separator and roots spelled one way, so the translation doesn't
+/// depend on the host and Windows paths can be matched anywhere.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum PathStyle {
+  /// `/` separates components, and case matters.
+  Posix,
+  /// `\` and `/` both separate components, paths may start with a drive,
+  /// a UNC share or a `\\?\` verbatim prefix, and case doesn't matter.
+  /// Verbatim prefixes of drives and shares are taken as the plain ones.
+  Windows,
+}
+
+impl PathStyle {
+  /// The style of the host's paths.
+  #[cfg(windows)]
+  pub fn host() -> PathStyle { PathStyle::Windows }
+  #[cfg(not(windows))]
+  pub fn host() -> PathStyle { PathStyle::Posix }
+
+  pub fn is_sep(&self, c: char) -> bool {
+    match *self {
+      PathStyle::Posix => c == '/',
+      PathStyle::Windows => c == '/' || c == '\\',
+    }
+  }
+
+  pub fn is_case_sensitive(&self) -> bool {
+    *self == PathStyle::Posix
+  }
+
+  // the length of the root at the start of `path`, including the separator
+  // after it, along with its canonical form
+  fn root(&self, path: &[u8]) -> Option<(usize, Vec<u8>)> {
+    if *self == PathStyle::Posix {
+      return if path.first() == Some(&b'/') { Some((1, b"/".to_vec())) } else { None };
+    }
+
+    let sep = |&: i: usize| i < path.len() && (path[i] == b'/' || path[i] == b'\\');
+    let end_of = |&: i: usize| range(i, path.len()).find(|&j| sep(j)).unwrap_or(path.len());
+    let is_drive = |&: c: &[u8]| {
+      c.len() == 2 && c[1] == b':' && (c[0] as char).is_alphabetic() && c[0] < 0x80
+    };
+
+    // a server and share, starting at `i`
+    let share = |&: i: usize| -> (usize, Vec<u8>) {
+      let server = end_of(i);
+      let end = if sep(server) { end_of(server + 1) } else { server };
+      let mut root = b"//".to_vec();
+      root.push_all(path.slice(i, server));
+
+      if sep(server) {
+        root.push(b'/');
+        root.push_all(path.slice(server + 1, end));
+      }
+
+      (end, root)
+    };
+
+    let (mut len, mut root) = if sep(0) && sep(1) {
+      if path.len() > 2 && (path[2] == b'?' || path[2] == b'.') && sep(3) {
+        let end = end_of(4);
+        let first = path.slice(4, end);
+
+        if path[2] == b'?' && first.eq_ignore_ascii_case(b"UNC") && sep(end) {
+          share(end + 1)
+        } else if path[2] == b'?' && is_drive(first) {
+          (end, first.to_ascii_uppercase())
+        } else {
+          let mut root = b"//".to_vec();
+          root.push(path[2]);
+          root.push(b'/');
+          root.push_all(first);
+          (end, root)
+        }
+      } else {
+        share(2)
+      }
+    } else if path.len() >= 2 && is_drive(path.slice_to(2)) {
+      (2, path.slice_to(2).to_ascii_uppercase())
+    } else if sep(0) {
+      return Some((1, b"/".to_vec()));
+    } else {
+      return None;
+    };
+
+    if sep(len) {
+      root.push(b'/');
+      len += 1;
+    }
+
+    Some((len, root))
+  }
+
+  /// Splits the root off a path or pattern, returning it in canonical
+  /// form, e.g. `C:/` for `\\?\c:\`.
+  pub fn split_root<'a>(&self, path: &'a str) -> (Option<String>, &'a str) {
+    match self.root(path.as_bytes()) {
+      Some((len, root)) => (String::from_utf8(root).ok(), path.slice_from(len)),
+      None => (None, path),
+    }
+  }
+
+  /// The canonical form of a path.
+  pub fn normalize(&self, path: &[u8]) -> Vec<u8> {
+    if *self == PathStyle::Posix {
+      return path.to_vec();
+    }
+
+    let (len, mut normalized) = self.root(path).unwrap_or((0, Vec::new()));
+
+    normalized.extend(path.slice_from(len).iter().map(|&b| {
+      if b == b'\\' { b'/' } else { b }
+    }));
+
+    normalized
+  }
+}
+
 // TODO: add original string here?
 pub struct Pattern {
   re: Regex,
   original: String,
+  style: PathStyle,
+  // for matching bytes that aren't UTF-8, which the regex can't
+  tokens: Vec<Token>,
+  // the name of each wildcard, if it has one
+  names: Vec<Option<String>>,
+}
+
+/// What each wildcard of a pattern matched, from `Pattern::captures`.
+///
+/// Every `*`, `?`, `**` and class is a capture, numbered from 0 in the
+/// order they appear; those written as `{name:*}` can be looked up by
+/// name too. `**` captures the directories it matched without the
+/// separator after them.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Captures {
+  values: Vec<String>,
+  names: Vec<Option<String>>,
+}
+
+impl Captures {
+  /// The number of wildcards.
+  pub fn len(&self) -> usize {
+    self.values.len()
+  }
+
+  pub fn get(&self, i: usize) -> Option<&str> {
+    self.values.get(i).map(|value| value.as_slice())
+  }
+
+  pub fn name(&self, name: &str) -> Option<&str> {
+    self.names.iter()
+      .position(|n| n.as_ref().map(|n| n.as_slice()) == Some(name))
+      .map(|i| self.values[i].as_slice())
+  }
+
+  /// The values in order.
+  pub fn values(&self) -> &[String] {
+    self.values.as_slice()
+  }
+}
+
+fn is_wildcard(token: &Token) -> bool {
+  match *token {
+    Char(..) => false,
+    _ => true,
+  }
+}
+
+// a character of a byte string, or a byte that isn't part of one
+#[derive(Copy, PartialEq)]
+enum Unit {
+  Char(char),
+  Byte(u8),
+}
+
+// splits `bytes` into characters, wherever it's valid UTF-8
+fn units(bytes: &[u8]) -> Vec<Unit> {
+  let mut units = Vec::new();
+  let mut i = 0;
+
+  while i < bytes.len() {
+    // the shortest valid sequence starting here, if any; UTF-8 being
+    // prefix-free, that's the one character it can start
+    let valid = range(1, ::std::cmp::min(4, bytes.len() - i) + 1)
+      .filter_map(|len| str::from_utf8(bytes.slice(i, i + len)).ok())
+      .next();
+
+    match valid {
+      Some(s) => {
+        units.push(Unit::Char(s.char_at(0)));
+        i += s.len();
+      }
+      None => {
+        units.push(Unit::Byte(bytes[i]));
+        i += 1;
+      }
+    }
+  }
+
+  units
+}
+
+fn in_set(specs: &Vec<CharSpecifier>, unit: Unit, fold: bool) -> bool {
+  let c = match unit {
+    Unit::Char(c) => c,
+    Unit::Byte(..) => return false,
+  };
+
+  let contains = |&: c: char| specs.iter().any(|spec| {
+    match *spec {
+      SingleChar(s) => c == s,
+      CharRange(a, b) => a <= c && c <= b,
+    }
+  });
+
+  contains(c) || fold && (contains(c.to_lowercase()) || contains(c.to_uppercase()))
+}
+
+// a character from each run of characters no token of `patterns` tells
+// apart, so that automata over them need only step over these. Canonical
+// paths in the Windows style have no `\`.
+fn representatives(patterns: &[&Pattern], style: PathStyle) -> Vec<char> {
+  let fold = !style.is_case_sensitive();
+  let mut bounds = BTreeSet::new();
+
+  {
+    let mut add = |&mut: lo: char, hi: char| {
+      bounds.insert(lo as u32);
+      bounds.insert(hi as u32 + 1);
+    };
+
+    add('/', '/');
+    add('\\', '\\');
+
+    for pattern in patterns.iter() {
+      for token in pattern.tokens.iter() {
+        let mut singles = Vec::new();
+
+        match *token {
+          Char(c) => singles.push(c),
+          AnyWithin(ref specs) | AnyExcept(ref specs) => {
+            for &spec in specs.iter() {
+              match spec {
+                SingleChar(c) => singles.push(c),
+                CharRange(a, b) => add(a, b),
+              }
+            }
+          }
+          _ => {}
+        }
+
+        for &c in singles.iter() {
+          add(c, c);
+
+          if fold {
+            add(c.to_lowercase(), c.to_lowercase());
+            add(c.to_uppercase(), c.to_uppercase());
+          }
+        }
+      }
+    }
+  }
+
+  // ranges of ASCII letters cover the other case too
+  if fold {
+    let letters: Vec<u32> = bounds.iter().map(|&b| b)
+      .filter(|&b| ('a' as u32 <= b && b <= 'z' as u32 + 1) || ('A' as u32 <= b && b <= 'Z' as u32 + 1))
+      .collect();
+
+    for &b in letters.iter() {
+      bounds.insert(if b >= 'a' as u32 { b - 32 } else { b + 32 });
+    }
+  }
+
+  // surrogates aren't characters
+  bounds.insert(0);
+  bounds.insert(0xD800);
+  bounds.insert(0xE000);
+  bounds.insert(0x110000);
+
+  let bounds: Vec<u32> = bounds.into_iter().collect();
+  let mut chars = Vec::new();
+
+  for pair in bounds.windows(2) {
+    let (lo, hi) = (pair[0], pair[1]);
+
+    // something readable, for witnesses
+    let pick = "abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_-."
+      .chars()
+      .find(|&c| lo <= c as u32 && (c as u32) < hi)
+      .or_else(|| char::from_u32(lo));
+
+    match pick {
+      Some('\\') if style == PathStyle::Windows => {}
+      Some(c) => chars.push(c),
+      None => {}
+    }
+  }
+
+  chars
 }
 
+/// What's wrong with a pattern.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub enum ErrorKind {
+  /// a `[` without a matching `]`
+  UnclosedClass,
+  /// `**` next to something other than a separator, like `a**` or `**b`
+  InvalidRecursiveWildcard,
+  /// three or more `*` in a row
+  TooManyStars,
+  /// a `{name:` not followed by a single `*`, `?` or class, and `}`
+  InvalidCapture,
+  /// a name given to two wildcards
+  DuplicateCaptureName,
+  /// a rewrite template with a `#` not followed by a number, `{name}` or
+  /// another `#`
+  InvalidTemplate,
+  /// a rewrite template referring to a wildcard the pattern doesn't have
+  NoSuchCapture,
+  /// the regex the pattern translates to failed to build
+  Regex(String),
+
+  /// a glob qualifier that doesn't exist
+  UnknownQualifier,
+  /// `o` or `O` followed by something other than `n`, `L` or `m`
+  UnknownSortOrder,
+  /// the `^`, `,` and `-` qualifier operators
+  UnsupportedOperator,
+  /// a qualifier missing its numeric argument
+  ExpectedNumber,
+  NumberTooLarge,
+  /// a `[` in the qualifiers without a matching `]`
+  UnclosedQualifierRange,
+  /// a `[0]` or `[0,n]` qualifier; indices start at 1
+  ZeroIndex,
+}
+
+impl ErrorKind {
+  pub fn description(&self) -> &str {
+    match *self {
+      ErrorKind::UnclosedClass => "unclosed character class",
+      ErrorKind::InvalidRecursiveWildcard =>
+        "recursive wildcards `**` must form a single path component, e.g. a/**/b",
+      ErrorKind::TooManyStars => "wildcards are either regular `*` or recursive `**`",
+      ErrorKind::InvalidCapture =>
+        "named captures take a single `*`, `?` or character class, as in `{name:*}`",
+      ErrorKind::DuplicateCaptureName => "capture name is already taken",
+      ErrorKind::InvalidTemplate =>
+        "`#` in templates is followed by a wildcard's number, `{name}` or another `#`",
+      ErrorKind::NoSuchCapture => "the pattern has no such wildcard",
+      ErrorKind::Regex(ref msg) => msg.as_slice(),
+      ErrorKind::UnknownQualifier => "unknown glob qualifier",
+      ErrorKind::UnknownSortOrder => "unknown sort order, expected one of `n`, `L` or `m`",
+      ErrorKind::UnsupportedOperator => "qualifier operators aren't supported",
+      ErrorKind::ExpectedNumber => "expected a number",
+      ErrorKind::NumberTooLarge => "number is too large",
+      ErrorKind::UnclosedQualifierRange => "unclosed `[` in glob qualifiers",
+      ErrorKind::ZeroIndex => "qualifier indices start at 1",
+    }
+  }
+}
+
+/// An invalid pattern, along with where in it the problem is.
+///
+/// Rewrite templates report their errors with it too: then `pattern` is the
+/// template, and `kind` is `InvalidTemplate` or `NoSuchCapture`.
+///
+/// Its `String` form quotes the pattern and points at the problem:
+///
+/// ```text
+/// unclosed character class
+///   src/[abc
+///       ^~~~
+/// ```
+#[derive(Clone, PartialEq, Eq)]
 pub struct Error {
-  pub pos: usize,
-  pub msg: String,
+  pub kind: ErrorKind,
+  /// the pattern as given, qualifiers and all
+  pub pattern: String,
+  /// the offending part of the pattern, in bytes
+  pub span: Range<usize>,
+  /// the same, in characters
+  pub char_span: Range<usize>,
+}
+
+impl Error {
+  /// An error covering the characters `chars` of `pattern`.
+  pub fn new(kind: ErrorKind, pattern: &str, chars: Range<usize>) -> Error {
+    Error {
+      kind: kind,
+      pattern: pattern.to_string(),
+      span: byte_span(pattern, &chars),
+      char_span: chars,
+    }
+  }
+
+  // the same error, in a pattern that `self.pattern` was taken from
+  // `offset` characters in
+  fn within(self, pattern: &str, offset: usize) -> Error {
+    Error::new(self.kind, pattern,
+               self.char_span.start + offset..self.char_span.end + offset)
+  }
+}
+
+// the bytes of `pattern` making up the characters `chars`
+fn byte_span(pattern: &str, chars: &Range<usize>) -> Range<usize> {
+  let byte = |&: n: usize| pattern.char_indices().nth(n).map_or(pattern.len(), |(i, _)| i);
+  byte(chars.start)..byte(chars.end)
+}
+
+// writes `message`, then `pattern` with the characters `chars` underlined
+fn point_at(f: &mut fmt::Formatter, message: &str, pattern: &str, chars: &Range<usize>)
+            -> fmt::Result {
+  let width = chars.end - chars.start;
+
+  try!(writeln!(f, "{}", message));
+  try!(writeln!(f, "  {}", pattern));
+  write!(f, "  {}^{}",
+         " ".repeat(chars.start),
+         "~".repeat(if width > 1 { width - 1 } else { 0 }))
+}
+
+impl fmt::String for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    point_at(f, self.kind.description(), self.pattern.as_slice(), &self.char_span)
+  }
 }
 
 impl fmt::Show for Error {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "Pattern syntax error near position {}: {}",
-           self.pos, self.msg)
+    write!(f, "Pattern syntax error at {}..{} of {:?}: {}",
+           self.char_span.start, self.char_span.end, self.pattern, self.kind.description())
+  }
+}
+
+impl error::Error for Error {
+  fn description(&self) -> &str {
+    self.kind.description()
+  }
+
+  fn detail(&self) -> Option<String> {
+    Some(self.to_string())
+  }
+}
+
+/// Everything wrong with a pattern, from `Pattern::diagnose`.
+#[derive(Clone, Show)]
+pub struct Diagnostics {
+  /// The pattern as parsed, with the text of each error taken literally.
+  pub tokens: Vec<Token>,
+  /// The characters of the pattern each token was parsed from.
+  pub spans: Vec<Range<usize>>,
+  /// The names given to wildcards, with the indices of their tokens.
+  pub names: Vec<(usize, String)>,
+  /// The errors, in the order they appear in the pattern.
+  pub errors: Vec<Error>,
+}
+
+impl Diagnostics {
+  pub fn is_ok(&self) -> bool {
+    self.errors.is_empty()
+  }
+}
+
+/// Something legal in a pattern, but likely a mistake.
+#[derive(Copy, Clone, PartialEq, Eq, Show)]
+pub enum LintKind {
+  /// `**/**`, which matches no more than `**` alone
+  RepeatedRecursive,
+  /// a `*` component next to `**`, as in `**/*/x`, which only differs
+  /// from `**/x` in needing one more directory
+  StarNextToRecursive,
+  /// a range of one character, like `[a-a]`
+  SingleCharRange,
+  /// a class member already covered by the members before it, like the
+  /// second `a` in `[aba]` or the `x` in `[a-zx]`
+  DuplicateClassMember,
+  /// a trailing separator, which only matches directories, after what
+  /// looks like a file name, as in `*.rs/`
+  TrailingSeparator,
+  /// a range running backwards, like `[z-a]`, whose three characters are
+  /// taken singly
+  ReversedRange,
+}
+
+impl LintKind {
+  pub fn description(&self) -> &str {
+    match *self {
+      LintKind::RepeatedRecursive => "repeated `**` matches nothing more than one",
+      LintKind::StarNextToRecursive =>
+        "`*` next to `**` only adds a required directory; `**` alone may be what's meant",
+      LintKind::SingleCharRange => "character range of a single character",
+      LintKind::DuplicateClassMember => "character class repeats a member",
+      LintKind::TrailingSeparator =>
+        "trailing separator only matches directories, but this looks like a file name",
+      LintKind::ReversedRange =>
+        "character range runs backwards, so its characters are taken singly",
+    }
   }
 }
 
+/// A suspicious part of a pattern, from `Pattern::lint`, along with a
+/// rewrite for it.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Warning {
+  pub kind: LintKind,
+  /// the suspicious part of the pattern, in bytes
+  pub span: Range<usize>,
+  /// the same, in characters
+  pub char_span: Range<usize>,
+  /// what to replace the span with
+  pub replacement: String,
+}
+
+impl Warning {
+  fn new(kind: LintKind, pattern: &str, chars: Range<usize>, replacement: String) -> Warning {
+    Warning {
+      kind: kind,
+      span: byte_span(pattern, &chars),
+      char_span: chars,
+      replacement: replacement,
+    }
+  }
+
+  /// `pattern`, the one the warning came from, with the rewrite applied.
+  pub fn apply(&self, pattern: &str) -> String {
+    format!("{}{}{}", pattern.slice_to(self.span.start), self.replacement,
+            pattern.slice_from(self.span.end))
+  }
+}
+
+// a class as written, `negated` for `[!...]`
+fn class_text(specs: &[CharSpecifier], negated: bool) -> String {
+  let mut text = String::from_str(if negated { "[!" } else { "[" });
+
+  for spec in specs.iter() {
+    match *spec {
+      SingleChar(c) => text.push(c),
+      CharRange(a, b) => {
+        text.push(a);
+        text.push('-');
+        text.push(b);
+      }
+    }
+  }
+
+  text.push(']');
+  text
+}
+
+// whether `spec` matches nothing that `specs` don't
+fn covered(specs: &[CharSpecifier], spec: CharSpecifier) -> bool {
+  let (lo, hi) = match spec {
+    SingleChar(c) => (c, c),
+    CharRange(a, b) => (a, b),
+  };
+
+  specs.iter().any(|s| match *s {
+    SingleChar(c) => lo == c && hi == c,
+    CharRange(a, b) => a <= lo && hi <= b,
+  })
+}
+
 impl Pattern {
   pub fn new(pattern: &str) -> Result<Pattern, Error> {
-    Pattern::parse(pattern).and_then(Pattern::compile)
-      .map(|r| Pattern { original: pattern.to_string(), re: r })
+    Pattern::with_style(pattern, PathStyle::host())
+  }
+
+  pub fn with_style(pattern: &str, style: PathStyle) -> Result<Pattern, Error> {
+    let Diagnostics { tokens, names, errors, .. } = Pattern::diagnose(pattern, style);
+
+    if let Some(err) = errors.into_iter().next() {
+      return Err(err);
+    }
+
+    let names = tokens.iter().enumerate()
+      .filter(|&(_, token)| is_wildcard(token))
+      .map(|(i, _)| names.iter().find(|&&(j, _)| j == i).map(|&(_, ref name)| name.clone()))
+      .collect();
+
+    let re = try!(Pattern::compile(tokens.clone(), style).map_err(|msg| {
+      Error::new(ErrorKind::Regex(msg), pattern, 0..pattern.chars().count())
+    }));
+
+    Ok(Pattern {
+      re: re,
+      original: pattern.to_string(),
+      style: style,
+      tokens: tokens,
+      names: names,
+    })
+  }
+
+  /// Parses a pattern without stopping at the first error, for reporting
+  /// all of them at once. Past each error parsing carries on as if its
+  /// text was literal, so `a/**b/[x/***` has three: the `**`, the unclosed
+  /// `[` and the `***`.
+  pub fn diagnose(pattern: &str, style: PathStyle) -> Diagnostics {
+    match style {
+      PathStyle::Posix => Pattern::parse(pattern),
+      PathStyle::Windows => {
+        // the root is taken literally, `?` and all
+        let (root, rest) = style.split_root(pattern);
+        let mut tokens: Vec<Token> = root.unwrap_or(String::new()).chars().map(Char).collect();
+        let offset = pattern.chars().count() - rest.chars().count();
+        let rest = rest.replace("\\", "/");
+
+        // the root's characters all come from all of it
+        let mut spans: Vec<Range<usize>> = tokens.iter().map(|_| 0..offset).collect();
+        let skipped = tokens.len();
+        let rest = Pattern::parse(rest.as_slice());
+        tokens.extend(rest.tokens.into_iter());
+        spans.extend(rest.spans.into_iter().map(|s| s.start + offset..s.end + offset));
+
+        Diagnostics {
+          tokens: tokens,
+          spans: spans,
+          names: rest.names.into_iter().map(|(i, name)| (i + skipped, name)).collect(),
+          errors: rest.errors.into_iter().map(|e| e.within(pattern, offset)).collect(),
+        }
+      }
+    }
+  }
+
+  /// Looks for parts of the pattern that are legal but likely mistakes,
+  /// in the order they appear.
+  pub fn lint(&self) -> Vec<Warning> {
+    let pattern = self.original.as_slice();
+    let chars: Vec<char> = pattern.chars().collect();
+    let Diagnostics { tokens, spans, .. } = Pattern::diagnose(pattern, self.style);
+    let style = self.style;
+    let mut warnings = Vec::new();
+
+    let is_sep = |&: i: usize| tokens.get(i).map_or(false, |t| match *t {
+      Char(c) => style.is_sep(c),
+      _ => false,
+    });
+
+    // a `*` making up a whole component
+    let lone_star = |&: i: usize| {
+      tokens[i] == AnySequence &&
+        (i == 0 || is_sep(i - 1) || tokens[i - 1] == AnyRecursiveSequence) &&
+        (i + 1 == tokens.len() || is_sep(i + 1))
+    };
+
+    let mut i = 0;
+
+    while i < tokens.len() {
+      match tokens[i] {
+        AnyRecursiveSequence => {
+          let mut end = i + 1;
+
+          while end < tokens.len() && tokens[end] == AnyRecursiveSequence {
+            end += 1;
+          }
+
+          let span = spans[i].start..spans[end - 1].end;
+
+          if chars.slice(span.start, span.end).iter().filter(|&&c| c == '*').count() > 2 {
+            let last = chars[span.end - 1];
+            let replacement = if style.is_sep(last) { format!("**{}", last) } else { "**".to_string() };
+            warnings.push(Warning::new(LintKind::RepeatedRecursive, pattern, span, replacement));
+          }
+
+          i = end;
+          continue;
+        }
+        AnySequence if lone_star(i) => {
+          // `**/*/`, or `*/**` with the separator between them
+          if i > 0 && tokens[i - 1] == AnyRecursiveSequence && i + 1 < tokens.len() {
+            warnings.push(Warning::new(LintKind::StarNextToRecursive, pattern,
+                                       spans[i].start..spans[i + 1].end, String::new()));
+          } else if i + 2 < tokens.len() && tokens[i + 2] == AnyRecursiveSequence {
+            warnings.push(Warning::new(LintKind::StarNextToRecursive, pattern,
+                                       spans[i].start..spans[i + 1].end, String::new()));
+          }
+        }
+        AnyWithin(ref specs) | AnyExcept(ref specs) => {
+          let negated = match tokens[i] { AnyExcept(..) => true, _ => false };
+          let span = spans[i].clone();
+
+          let single: Vec<CharSpecifier> = specs.iter().map(|&spec| match spec {
+            CharRange(a, b) if a == b => SingleChar(a),
+            spec => spec,
+          }).collect();
+
+          if single != *specs {
+            warnings.push(Warning::new(LintKind::SingleCharRange, pattern, span.clone(),
+                                       class_text(single.as_slice(), negated)));
+          }
+
+          let mut distinct: Vec<CharSpecifier> = Vec::new();
+
+          for &spec in specs.iter() {
+            if !covered(distinct.as_slice(), spec) {
+              distinct.push(spec);
+            }
+          }
+
+          if distinct != *specs {
+            warnings.push(Warning::new(LintKind::DuplicateClassMember, pattern, span.clone(),
+                                       class_text(distinct.as_slice(), negated)));
+          }
+
+          // suggest the range meant, as that's what the characters are
+          // most likely for
+          let open = if negated { 2 } else { 1 };
+          let (_, reversed) = Pattern::parse_character_class(chars.as_slice(), span.start + open,
+                                                             span.end - 1);
+
+          for j in reversed.into_iter() {
+            warnings.push(Warning::new(LintKind::ReversedRange, pattern, j..j + 3,
+                                       format!("{}-{}", chars[j + 2], chars[j])));
+          }
+        }
+        _ => {}
+      }
+
+      i += 1;
+    }
+
+    // the last component before a trailing separator, if it has something
+    // like an extension
+    let last = tokens.len();
+
+    if last > 1 && is_sep(last - 1) {
+      let mut start = last - 1;
+
+      while start > 0 && !is_sep(start - 1) && tokens[start - 1] != AnyRecursiveSequence {
+        start -= 1;
+      }
+
+      let name = tokens.slice(start, last - 1);
+      let extension = name.iter().rposition(|t| *t == Char('.')).map_or(false, |dot| {
+        dot > 0 && dot + 1 < name.len() &&
+          name.slice_from(dot + 1).iter().all(|t| match *t {
+            Char(c) => c.is_alphanumeric(),
+            _ => false,
+          })
+      });
+
+      if extension {
+        warnings.push(Warning::new(LintKind::TrailingSeparator, pattern,
+                                   spans[last - 1].clone(), String::new()));
+      }
+    }
+
+    warnings
+  }
+
+  /// The pattern as a syntax tree.
+  pub fn ast(&self) -> Ast {
+    Ast::parse(self.original.as_slice(), self.style).ok().expect("pattern was already parsed")
+  }
+
+  /// Whether the pattern matches only itself, having no wildcards or
+  /// classes.
+  pub fn is_literal(&self) -> bool {
+    self.tokens.iter().all(|token| match *token {
+      Char(..) => true,
+      _ => false,
+    })
+  }
+
+  /// Whether the pattern has `?`, `*` or `**` in it.
+  pub fn has_wildcards(&self) -> bool {
+    self.tokens.iter().any(|token| match *token {
+      AnyChar | AnySequence | AnyRecursiveSequence => true,
+      _ => false,
+    })
+  }
+
+  /// The name of each wildcard, in order, for those that have one.
+  pub fn capture_names(&self) -> &[Option<String>] {
+    self.names.as_slice()
+  }
+
+  /// The regex the pattern translates to, which matches paths in the
+  /// style's canonical form.
+  pub fn regex(&self) -> &Regex {
+    &self.re
+  }
+
+  pub fn style(&self) -> PathStyle {
+    self.style
   }
 
   pub fn as_str<'a>(&'a self) -> &'a str {
@@ -57,21 +841,196 @@ impl Pattern {
   }
 
   pub fn matches(&self, str: &str) -> bool {
-    self.re.is_match(str)
+    match self.style {
+      PathStyle::Posix => self.re.is_match(str),
+      PathStyle::Windows => self.matches_bytes(str.as_bytes()),
+    }
   }
 
-  pub fn matches_path(&self, path: &Path) -> bool {
-    path.as_str().map_or(false, |s| {
-      self.matches(s)
+  /// Like `matches`, but for strings that may not be UTF-8, such as the
+  /// names of files on Unix. Bytes that aren't part of a valid UTF-8
+  /// sequence are taken as characters of their own: wildcards match them,
+  /// but no literal or character class does.
+  pub fn matches_bytes(&self, bytes: &[u8]) -> bool {
+    let normalized = self.style.normalize(bytes);
+
+    match str::from_utf8(normalized.as_slice()) {
+      Ok(s) => self.re.is_match(s),
+      Err(..) => self.matches_units(units(normalized.as_slice()).as_slice()),
+    }
+  }
+
+  /// What the wildcards matched, if the pattern matches `str`. Like
+  /// `matches`, a match may start anywhere in `str`; the captures are from
+  /// the leftmost one, and of the style's canonical form of `str`.
+  pub fn captures(&self, str: &str) -> Option<Captures> {
+    let normalized;
+    let str = match self.style {
+      PathStyle::Posix => str,
+      PathStyle::Windows => {
+        normalized = String::from_utf8(self.style.normalize(str.as_bytes())).unwrap();
+        normalized.as_slice()
+      }
+    };
+
+    let caps = match self.re.captures(str) {
+      Some(caps) => caps,
+      None => return None,
+    };
+
+    let wildcards = self.tokens.iter().filter(|token| is_wildcard(*token));
+    let values = wildcards.enumerate().map(|(i, token)| {
+      let value = caps.at(i + 1).unwrap_or("");
+
+      if *token == AnyRecursiveSequence && value.ends_with("/") {
+        value.slice_to(value.len() - 1).to_string()
+      } else {
+        value.to_string()
+      }
+    }).collect();
+
+    Some(Captures {
+      values: values,
+      names: self.names.clone(),
     })
   }
 
+  pub fn matches_path(&self, path: &Path) -> bool {
+    self.matches_bytes(path.as_vec())
+  }
+
+  // runs the tokens as an automaton over `units`, with the same semantics
+  // as the regex: a match may start anywhere, but must run to the end
+  fn matches_units(&self, units: &[Unit]) -> bool {
+    let mut states = self.start();
+
+    for &unit in units.iter() {
+      states = self.step(states.as_slice(), unit);
+    }
+
+    states[self.tokens.len()]
+  }
+
+  // the automaton's states before reading anything: state `i` is having
+  // matched the first `i` tokens
+  fn start(&self) -> Vec<bool> {
+    let mut states: Vec<bool> = range(0, self.tokens.len() + 1).map(|i| i == 0).collect();
+    self.close(&mut states);
+    states
+  }
+
+  // `*` and `**` match nothing, too
+  fn close(&self, states: &mut Vec<bool>) {
+    for (i, token) in self.tokens.iter().enumerate() {
+      if states[i] {
+        match *token {
+          AnySequence | AnyRecursiveSequence => states[i + 1] = true,
+          _ => {}
+        }
+      }
+    }
+  }
+
+  fn step(&self, states: &[bool], unit: Unit) -> Vec<bool> {
+    let fold = !self.style.is_case_sensitive();
+
+    let same = |&: unit: Unit, c: char| {
+      match unit {
+        Unit::Char(u) => u == c || fold && u.to_lowercase() == c.to_lowercase(),
+        Unit::Byte(..) => false,
+      }
+    };
+
+    // a match can start at any unit
+    let mut next: Vec<bool> = range(0, states.len()).map(|i| i == 0).collect();
+
+    for (i, token) in self.tokens.iter().enumerate() {
+      if !states[i] {
+        continue;
+      }
+
+      match *token {
+        Char(c) => if same(unit, c) { next[i + 1] = true },
+        AnyChar => next[i + 1] = true,
+        AnySequence => if unit != Unit::Char('/') { next[i] = true },
+        AnyRecursiveSequence => next[i] = true,
+        AnyWithin(ref specs) => if in_set(specs, unit, fold) { next[i + 1] = true },
+        AnyExcept(ref specs) => if !in_set(specs, unit, fold) { next[i + 1] = true },
+      }
+    }
+
+    self.close(&mut next);
+    next
+  }
+
+  /// Whether every path this pattern matches is matched by `other`, as
+  /// when a rule can never apply because an earlier one takes all its
+  /// paths. If not, the error is a path only this pattern matches.
+  ///
+  /// Paths are taken in the style's canonical form.
+  ///
+  /// # Panics
+  ///
+  /// If the patterns have different styles.
+  pub fn is_subset_of(&self, other: &Pattern) -> Result<(), String> {
+    match self.search(other, |&: a, b| a && !b) {
+      Some(path) => Err(path),
+      None => Ok(()),
+    }
+  }
+
+  /// A path both patterns match, if there's one.
+  ///
+  /// # Panics
+  ///
+  /// If the patterns have different styles.
+  pub fn intersects(&self, other: &Pattern) -> Option<String> {
+    self.search(other, |&: a, b| a && b)
+  }
+
+  // runs both automata side by side, breadth first, for the shortest path
+  // on which whether each accepts satisfies `found`
+  fn search<F>(&self, other: &Pattern, found: F) -> Option<String>
+    where F: Fn(bool, bool) -> bool
+  {
+    assert!(self.style == other.style, "can't compare patterns of different styles");
+
+    let alphabet = representatives(&[self, other], self.style);
+    let (a_end, b_end) = (self.tokens.len(), other.tokens.len());
+    let mut seen = HashSet::new();
+    let mut queue = RingBuf::new();
+
+    queue.push_back((self.start(), other.start(), String::new()));
+
+    while let Some((a, b, path)) = queue.pop_front() {
+      if found(a[a_end], b[b_end]) {
+        return Some(path);
+      }
+
+      if !seen.insert((a.clone(), b.clone())) {
+        continue;
+      }
+
+      for &c in alphabet.iter() {
+        let next = (self.step(a.as_slice(), Unit::Char(c)), other.step(b.as_slice(), Unit::Char(c)));
+
+        if !seen.contains(&next) {
+          let mut path = path.clone();
+          path.push(c);
+          queue.push_back((next.0, next.1, path));
+        }
+      }
+    }
+
+    None
+  }
+
   pub fn escape(s: &str) -> String {
     let mut escaped = String::new();
 
     for c in s.chars() {
       match c {
-        '?' | '*' | '[' | ']' => {
+        '?' | '*' | '[' | ']' | '{' => {
           escaped.push('[');
           escaped.push(c);
           escaped.push(']');
@@ -85,12 +1044,21 @@ impl Pattern {
     return escaped;
   }
 
-  fn parse(pattern: &str) -> Result<Vec<Token>, Error> {
+  // the tokens of `pattern` and their spans, recovering from errors by
+  // taking their text literally
+  fn parse(pattern: &str) -> Diagnostics {
     let chars = pattern.chars().collect::<Vec<_>>();
     let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    let mut names = Vec::new();
+    let mut errors = Vec::new();
     let mut i = 0;
+    let mut start = 0;
 
     while i < chars.len() {
+      Pattern::cover(&mut spans, tokens.len(), start..i);
+      start = i;
+
       match chars[i] {
         '?' => {
           tokens.push(AnyChar);
@@ -106,11 +1074,8 @@ impl Pattern {
           let count = i - old;
 
           if count > 2 {
-            return Err(
-              Error {
-                pos: old + 2,
-                msg: "wildcards are either regular `*` or recursive `**`".to_string(),
-              })
+            errors.push(Error::new(ErrorKind::TooManyStars, pattern, old..i));
+            tokens.extend(range(0, count).map(|_| Char('*')));
           }
 
           else if count == 2 {
@@ -129,23 +1094,13 @@ impl Pattern {
                   true
                   // `**` ends in non-separator
                 } else {
-                    return Err(
-                      Error  {
-                        pos: i,
-                        msg: concat!(
-                          "recursive wildcards `**` must form ",
-                          "a single path component, e.g. a/**/b").to_string(),
-                        });
+                  errors.push(Error::new(ErrorKind::InvalidRecursiveWildcard, pattern, old..i));
+                  false
                 }
                 // `**` begins with non-separator
               } else {
-                return Err(
-                  Error  {
-                    pos: old - 1,
-                    msg: concat!(
-                      "recursive wildcards `**` must form ",
-                      "a single path component, e.g. a/**/b").to_string(),
-                    });
+                errors.push(Error::new(ErrorKind::InvalidRecursiveWildcard, pattern, old..i));
+                false
               };
 
             let tokens_len = tokens.len();
@@ -155,42 +1110,68 @@ impl Pattern {
               if !(tokens_len > 1 && tokens[tokens_len - 1] == AnyRecursiveSequence) {
                 tokens.push(AnyRecursiveSequence);
               }
+            } else {
+              tokens.push(Char('*'));
+              tokens.push(Char('*'));
             }
           } else {
             tokens.push(AnySequence);
           }
         }
         '[' => {
-          if i <= chars.len() - 4 && chars[i + 1] == '!' {
-            match chars.slice_from(i + 3).position_elem(&']') {
-              None => (),
-              Some(j) => {
-                let chars = chars.slice(i + 2, i + 3 + j);
-                let cs = Pattern::parse_character_class(chars);
-                tokens.push(AnyExcept(cs));
-                i += j + 4;
-                continue;
-              }
+          match Pattern::parse_class(&chars, i) {
+            Some((token, next)) => {
+              tokens.push(token);
+              i = next;
+            }
+            None => {
+              errors.push(Error::new(ErrorKind::UnclosedClass, pattern, i..chars.len()));
+              tokens.push(Char('['));
+              i += 1;
             }
           }
+        }
+        '{' => {
+          // `{name:` followed by a wildcard names it; other braces are
+          // literal
+          let colon = range(i + 1, chars.len()).find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'));
+
+          let start = match colon {
+            Some(colon) if colon > i + 1 && chars[colon] == ':' && !chars[i + 1].is_digit(10) &&
+                           colon + 1 < chars.len() && "*?[".contains_char(chars[colon + 1]) => colon + 1,
+            _ => {
+              tokens.push(Char('{'));
+              i += 1;
+              continue;
+            }
+          };
+
+          let name: String = chars.slice(i + 1, start - 1).iter().map(|c| *c).collect();
+
+          let wildcard = match chars[start] {
+            '*' if chars.get(start + 1) != Some(&'*') => Some((AnySequence, start + 1)),
+            '?' => Some((AnyChar, start + 1)),
+            '[' => Pattern::parse_class(&chars, start),
+            _ => None,
+          };
 
-          else if i <= chars.len() - 3 && chars[i + 1] != '!' {
-            match chars.slice_from(i + 2).position_elem(&']') {
-              None => (),
-              Some(j) => {
-                let cs = Pattern::parse_character_class(chars.slice(i + 1, i + 2 + j));
-                tokens.push(AnyWithin(cs));
-                i += j + 3;
-                continue;
+          match wildcard {
+            Some((token, next)) if chars.get(next) == Some(&'}') => {
+              if names.iter().any(|&(_, ref other)| *other == name) {
+                errors.push(Error::new(ErrorKind::DuplicateCaptureName, pattern, i..next + 1));
+              } else {
+                names.push((tokens.len(), name));
               }
+
+              tokens.push(token);
+              i = next + 1;
+            }
+            _ => {
+              errors.push(Error::new(ErrorKind::InvalidCapture, pattern, i..start + 1));
+              tokens.push(Char('{'));
+              i += 1;
             }
           }
-
-          // if we get here then this is not a valid range pattern
-          return Err(
-            Error  {
-              pos: i,
-              msg: "invalid range pattern".to_string()});
         }
         c => {
           tokens.push(Char(c));
@@ -199,24 +1180,73 @@ impl Pattern {
       }
     }
 
-    Ok(tokens)
+    Pattern::cover(&mut spans, tokens.len(), start..i);
+
+    Diagnostics {
+      tokens: tokens,
+      spans: spans,
+      names: names,
+      errors: errors,
+    }
+  }
+
+  // the class starting with the `[` at `chars[i]`, and the index past it,
+  // unless it's unclosed
+  fn parse_class(chars: &Vec<char>, i: usize) -> Option<(Token, usize)> {
+    if i + 4 <= chars.len() && chars[i + 1] == '!' {
+      chars.slice_from(i + 3).position_elem(&']').map(|j| {
+        let (cs, _) = Pattern::parse_character_class(chars.as_slice(), i + 2, i + 3 + j);
+        (AnyExcept(cs), i + j + 4)
+      })
+    } else if i + 3 <= chars.len() && chars[i + 1] != '!' {
+      chars.slice_from(i + 2).position_elem(&']').map(|j| {
+        let (cs, _) = Pattern::parse_character_class(chars.as_slice(), i + 1, i + 2 + j);
+        (AnyWithin(cs), i + j + 3)
+      })
+    } else {
+      None
+    }
+  }
+
+  // gives the tokens parsed from `chars` their span, or stretches the last
+  // span over them when they were folded into the token before
+  fn cover(spans: &mut Vec<Range<usize>>, len: usize, chars: Range<usize>) {
+    if spans.len() == len {
+      if let Some(last) = spans.last_mut() {
+        last.end = chars.end;
+      }
+    }
+
+    while spans.len() < len {
+      spans.push(chars.clone());
+    }
   }
 
-  fn parse_character_class(s: &[char]) -> Vec<CharSpecifier> {
+  // the class between `chars[start]` and `chars[end]`, along with where
+  // each backwards range starts; their characters are taken singly
+  fn parse_character_class(chars: &[char], start: usize, end: usize)
+                           -> (Vec<CharSpecifier>, Vec<usize>) {
     let mut cs = Vec::new();
-    let mut i = 0;
+    let mut reversed = Vec::new();
+    let mut i = start;
+
+    while i < end {
+      if i + 3 <= end && chars[i + 1] == '-' {
+        if chars[i] > chars[i + 2] {
+          reversed.push(i);
+          cs.extend(chars.slice(i, i + 3).iter().map(|&c| SingleChar(c)));
+        } else {
+          cs.push(CharRange(chars[i], chars[i + 2]));
+        }
 
-    while i < s.len() {
-      if i + 3 <= s.len() && s[i + 1] == '-' {
-        cs.push(CharRange(s[i], s[i + 2]));
         i += 3;
       } else {
-        cs.push(SingleChar(s[i]));
+        cs.push(SingleChar(chars[i]));
         i += 1;
       }
     }
 
-    return cs;
+    (cs, reversed)
   }
 
   fn emit_set(pattern: &mut String, specs: &Vec<CharSpecifier>) {
@@ -250,18 +1280,27 @@ impl Pattern {
     return escaped;
   }
 
-  fn compile(tokens: Vec<Token>) -> Result<Regex, Error> {
+  // paths are matched in canonical form, so `/` is the only separator
+  fn compile(tokens: Vec<Token>, style: PathStyle) -> Result<Regex, String> {
     let mut re = String::new();
 
+    if !style.is_case_sensitive() {
+      re.push_str("(?i)");
+    }
+
     for token in tokens.iter() {
+      // each wildcard is a group, for `captures`
+      if is_wildcard(token) {
+        re.push('(');
+      }
+
       match *token {
         Char(c) => re.push_str(Pattern::escape_regex_char(c).as_slice()),
         AnyChar => re.push('.'),
         AnySequence =>
-          re.push_str(
-            format!(r"[^{sep}]*",
-                    sep = Pattern::escape_regex_char(path::SEP).as_slice()).as_slice()),
-        AnyRecursiveSequence => re.push_str(".*"),
+          re.push_str(r"[^/]*"),
+        // lazily, so that wildcards after it capture as much as they can
+        AnyRecursiveSequence => re.push_str(".*?"),
         AnyWithin(ref specs) => {
           re.push('[');
           Pattern::emit_set(&mut re, specs);
@@ -273,36 +1312,41 @@ impl Pattern {
           re.push(']');
         }
       }
+
+      if is_wildcard(token) {
+        re.push(')');
+      }
     }
 
     re.push_str(r"\z(?ms)");
 
-    Regex::new(re.as_slice())
-      .map_err(|e| Error { pos: e.pos, msg: e.msg })
+    Regex::new(re.as_slice()).map_err(|e| e.msg)
   }
 }
 
 impl fmt::Show for Pattern {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{}", self.re)
+    write!(f, "Pattern({:?})", self.original)
   }
 }
 
 impl fmt::String for Pattern {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{}", self.re)
+    write!(f, "{}", self.original)
   }
 }
 
 impl PartialEq for Pattern {
   fn eq(&self, other: &Pattern) -> bool {
-    self.to_string() == other.to_string()
+    self.original == other.original && self.style == other.style
   }
 }
 
 #[cfg(test)]
 mod test {
-  use super::Pattern;
+  use super::{Pattern, PathStyle, ErrorKind, LintKind};
+  use super::Token::{Char, AnyChar, AnyWithin};
+  use super::CharSpecifier::SingleChar;
 
   #[test]
   fn match_dir() {
@@ -313,29 +1357,162 @@ mod test {
 
   #[test]
   fn translation() {
-    let pat = Pattern::new("some/**/te*t.t?t").unwrap().to_string();
-    assert!(pat == r"some/.*te[^/]*t\.t.t\z(?ms)");
+    let pat = Pattern::new("some/**/te*t.t?t").unwrap().regex().to_string();
+    assert!(pat == r"some/(.*?)te([^/]*)t\.t(.)t\z(?ms)");
+
+    let pat = Pattern::new("some/*/te*t.t?t").unwrap().regex().to_string();
+    assert!(pat == r"some/([^/]*)/te([^/]*)t\.t(.)t\z(?ms)");
+
+    let pat = Pattern::new("one/**").unwrap().regex().to_string();
+    assert!(pat == r"one/(.*?)\z(?ms)");
 
-    let pat = Pattern::new("some/*/te*t.t?t").unwrap().to_string();
-    assert!(pat == r"some/[^/]*/te[^/]*t\.t.t\z(?ms)");
+    let pat = Pattern::new("some/**/te*t.t?t").unwrap();
+    assert_eq!(pat.to_string().as_slice(), "some/**/te*t.t?t");
+  }
 
-    let pat = Pattern::new("one/**").unwrap().to_string();
-    assert!(pat == r"one/.*\z(?ms)");
+  #[test]
+  fn literals() {
+    assert!(Pattern::new("src/lib.rs").unwrap().is_literal());
+    assert!(!Pattern::new("src/lib.rs").unwrap().has_wildcards());
+
+    let pat = Pattern::new("src/[lm]*.rs").unwrap();
+    assert!(!pat.is_literal());
+    assert!(pat.has_wildcards());
+
+    let pat = Pattern::new("src/[lm]ib.rs").unwrap();
+    assert!(!pat.is_literal());
+    assert!(!pat.has_wildcards());
+    assert_eq!(pat.ast().to_string().as_slice(), "src/[lm]ib.rs");
   }
 
   #[test]
   fn errors() {
     let err = Pattern::new("a/**b").unwrap_err();
-    assert!(err.pos == 4);
+    assert_eq!(err.kind, ErrorKind::InvalidRecursiveWildcard);
+    assert_eq!(err.char_span, 2..4);
 
     let err = Pattern::new("a/bc**").unwrap_err();
-    assert!(err.pos == 3);
+    assert_eq!(err.kind, ErrorKind::InvalidRecursiveWildcard);
+    assert_eq!(err.char_span, 4..6);
 
     let err = Pattern::new("a/*****").unwrap_err();
-    assert!(err.pos == 4);
+    assert_eq!(err.kind, ErrorKind::TooManyStars);
+    assert_eq!(err.char_span, 2..7);
 
     let err = Pattern::new("a/b**c**d").unwrap_err();
-    assert!(err.pos == 2);
+    assert_eq!(err.char_span, 3..5);
+
+    // backwards ranges are allowed, as their three characters
+    let pat = Pattern::new("[a-c][z-a]").unwrap();
+    assert!(pat.matches("b-") && pat.matches("cz"));
+    assert!(!pat.matches("bm"));
+
+    // spans count characters and bytes separately
+    let err = Pattern::new("été/[!é").unwrap_err();
+    assert_eq!(err.char_span, 4..7);
+    assert_eq!(err.span, 6..10);
+  }
+
+  #[test]
+  fn diagnose() {
+    let diagnostics = Pattern::diagnose("a/**b/[x/***", PathStyle::Posix);
+    let errors: Vec<_> = diagnostics.errors.iter().map(|e| (e.kind.clone(), e.char_span.clone())).collect();
+
+    assert_eq!(errors, vec![
+      (ErrorKind::InvalidRecursiveWildcard, 2..4),
+      (ErrorKind::UnclosedClass, 6..12),
+      (ErrorKind::TooManyStars, 9..12),
+    ]);
+    assert!(diagnostics.tokens.iter().all(|t| match *t { Char(..) => true, _ => false }));
+    assert_eq!(diagnostics.tokens.len(), 12);
+
+    // backwards ranges are taken as their characters
+    let diagnostics = Pattern::diagnose("[z-a]?", PathStyle::Posix);
+    assert!(diagnostics.is_ok());
+    assert_eq!(diagnostics.tokens, vec![
+      AnyWithin(vec![SingleChar('z'), SingleChar('-'), SingleChar('a')]),
+      AnyChar,
+    ]);
+
+    let diagnostics = Pattern::diagnose(r"C:\[a\**x", PathStyle::Windows);
+    assert_eq!(diagnostics.errors.iter().map(|e| e.char_span.start).collect::<Vec<_>>(),
+               vec![3, 6]);
+
+    assert!(Pattern::diagnose("a/**/b", PathStyle::Posix).is_ok());
+  }
+
+  #[test]
+  fn lint() {
+    let lint = |&: pattern: &str| -> Vec<(LintKind, String)> {
+      Pattern::with_style(pattern, PathStyle::Posix).unwrap().lint().iter()
+        .map(|w| (w.kind, w.apply(pattern)))
+        .collect()
+    };
+    let warn = |&: kind: LintKind, fixed: &str| vec![(kind, fixed.to_string())];
+
+    assert_eq!(lint("src/**/**/*.rs"), warn(LintKind::RepeatedRecursive, "src/**/*.rs"));
+    assert_eq!(lint("**/**"), warn(LintKind::RepeatedRecursive, "**"));
+    assert_eq!(lint("src/**/*/mod.rs"), warn(LintKind::StarNextToRecursive, "src/**/mod.rs"));
+    assert_eq!(lint("*/**/x"), warn(LintKind::StarNextToRecursive, "**/x"));
+    assert_eq!(lint("[a-a]x"), warn(LintKind::SingleCharRange, "[a]x"));
+    assert_eq!(lint("[!a-zxbé]"), warn(LintKind::DuplicateClassMember, "[!a-zé]"));
+    assert_eq!(lint("src/*.rs/"), warn(LintKind::TrailingSeparator, "src/*.rs"));
+    assert_eq!(lint("[!0z-a]"), warn(LintKind::ReversedRange, "[!0a-z]"));
+
+    assert_eq!(lint("é/[a-aa]"), vec![
+      (LintKind::SingleCharRange, "é/[aa]".to_string()),
+      (LintKind::DuplicateClassMember, "é/[a-a]".to_string()),
+    ]);
+
+    for pattern in ["src/**/*.rs", "**/*", "[a-z]*/", ".config/", "*.d*/"].iter() {
+      assert_eq!(lint(*pattern), vec![]);
+    }
+
+    let warnings = Pattern::with_style(r"C:\x\**\**\*.TXT\", PathStyle::Windows).unwrap().lint();
+    assert_eq!(warnings.iter().map(|w| (w.kind, w.char_span.clone())).collect::<Vec<_>>(),
+               vec![(LintKind::RepeatedRecursive, 5..11), (LintKind::TrailingSeparator, 16..17)]);
+    assert_eq!(warnings[0].replacement.as_slice(), r"**\");
+  }
+
+  #[test]
+  fn captures() {
+    let pat = Pattern::with_style("crates/{crate:*}/Cargo.toml", PathStyle::Posix).unwrap();
+    let caps = pat.captures("crates/glob/Cargo.toml").unwrap();
+    assert_eq!(caps.name("crate"), Some("glob"));
+    assert_eq!(caps.get(0), Some("glob"));
+    assert!(pat.matches("crates/glob/Cargo.toml"));
+    assert!(pat.captures("crates/Cargo.toml").is_none());
+
+    let pat = Pattern::with_style("src/**/{stem:*}.[rt]s?", PathStyle::Posix).unwrap();
+    let caps = pat.captures("src/a/b/lib.rsx").unwrap();
+    assert_eq!(caps.values(), ["a/b", "lib", "r", "x"].iter().map(|s| s.to_string())
+                                .collect::<Vec<_>>().as_slice());
+    assert_eq!(caps.name("stem"), Some("lib"));
+    assert_eq!(pat.captures("src/lib.rsx").unwrap().get(0), Some(""));
+
+    let pat = Pattern::with_style(r"C:\Users\{user:*}\*.TXT", PathStyle::Windows).unwrap();
+    let caps = pat.captures(r"c:\users\Ann\notes.txt").unwrap();
+    assert_eq!(caps.name("user"), Some("Ann"));
+    assert_eq!(caps.get(1), Some("notes"));
+
+    // other braces are literal
+    assert!(Pattern::new("{a,b}/{1:x}").unwrap().matches("{a,b}/{1:x}"));
+
+    let err = Pattern::new("{dir:**}/x").unwrap_err();
+    assert_eq!(err.kind, ErrorKind::InvalidCapture);
+    assert_eq!(err.char_span, 0..6);
+    assert_eq!(Pattern::new("{a:?}{a:?}").unwrap_err().kind, ErrorKind::DuplicateCaptureName);
+    assert_eq!(Pattern::new("{a:[x}").unwrap_err().kind, ErrorKind::InvalidCapture);
+  }
+
+  #[test]
+  fn error_rendering() {
+    let err = Pattern::new("src/[abc").unwrap_err();
+    assert_eq!(err.to_string().as_slice(),
+               "unclosed character class\n  src/[abc\n      ^~~~");
+
+    let err = Pattern::new("a/**b").unwrap_err();
+    assert_eq!(err.to_string().lines().last(), Some("    ^~"));
   }
 
   #[test]
@@ -483,8 +1660,10 @@ mod test {
 
   #[test]
   fn unclosed_bracket() {
-    // TODO: assert error position
-    // unclosed `[` should be treated literally
+    let err = Pattern::new("abc[def").unwrap_err();
+    assert_eq!(err.kind, ErrorKind::UnclosedClass);
+    assert_eq!(err.char_span, 3..7);
+
     assert!(Pattern::new("abc[def").is_err());
     assert!(Pattern::new("abc[!def").is_err());
     assert!(Pattern::new("abc[").is_err());
@@ -518,6 +1697,83 @@ mod test {
     let s = "_[_]_?_*_!_";
     assert_eq!(Pattern::escape(s), "_[[]_[]]_[?]_[*]_!_".to_string());
     assert!(Pattern::new(Pattern::escape(s).as_slice()).unwrap().matches(s));
+
+    let s = "{a:?}";
+    assert_eq!(Pattern::escape(s), "[{]a:[?]}".to_string());
+    assert!(Pattern::new(Pattern::escape(s).as_slice()).unwrap().matches(s));
+    assert!(!Pattern::new(Pattern::escape(s).as_slice()).unwrap().matches("x"));
+  }
+
+  #[test]
+  fn matches_bytes() {
+    let pat = Pattern::new("caf*.txt").unwrap();
+    assert!(pat.matches_bytes(b"caf\xe9.txt"));
+    assert!(pat.matches_bytes(b"some/caf\xe9\xff.txt"));
+    assert!(!pat.matches_bytes(b"caf\xe9/x.txt"));
+    assert!(pat.matches_bytes("caf\u{e9}.txt".as_bytes()));
+
+    assert!(Pattern::new("a?b").unwrap().matches_bytes(b"a\x80b"));
+    assert!(!Pattern::new("a?b").unwrap().matches_bytes(b"a\x80\x80b"));
+    assert!(Pattern::new("a[!x]b").unwrap().matches_bytes(b"a\xe9b"));
+    assert!(!Pattern::new("a[a-z]b").unwrap().matches_bytes(b"a\xe9b"));
+    assert!(Pattern::new("a/**").unwrap().matches_bytes(b"a/\xe9/c"));
+
+    // a stray byte isn't the character with the same value
+    let e = Pattern::new("\u{e9}").unwrap();
+    assert!(!e.matches_bytes(b"\xe9"));
+    assert!(e.matches_bytes("\u{e9}".as_bytes()));
+
+    let path = Path::new(b"caf\xe9.txt".as_slice());
+    assert!(pat.matches_path(&path));
+    assert!(!Pattern::new("caf\u{e9}.txt").unwrap().matches_path(&path));
+  }
+
+  #[test]
+  fn windows_style() {
+    let windows = |&: p: &str| Pattern::with_style(p, PathStyle::Windows).unwrap();
+
+    let pat = windows(r"C:\Users\*\AppData\**");
+    assert!(pat.matches(r"C:\Users\bob\AppData\Local\Temp"));
+    assert!(pat.matches("c:/users/bob/appdata/local"));
+    assert!(pat.matches(r"\\?\C:\Users\bob\AppData\x"));
+    assert!(!pat.matches(r"C:\Users\bob\Documents\AppData\x"));
+    assert!(!pat.matches(r"D:\Users\bob\AppData\x"));
+
+    let pat = windows(r"\\server\share\*.log");
+    assert!(pat.matches(r"\\?\UNC\server\share\a.log"));
+    assert!(pat.matches("//SERVER/share/b.LOG"));
+    assert!(!pat.matches(r"\\server\other\a.log"));
+
+    // verbatim roots are literal
+    let pat = windows(r"\\?\pictures\*");
+    assert!(pat.matches(r"\\?\pictures\a.png"));
+    assert!(!pat.matches(r"\\x\pictures\a.png"));
+
+    assert!(windows("[a-c]*.TXT").matches_bytes(b"B\xff.txt"));
+    assert!(!Pattern::with_style("*.TXT", PathStyle::Posix).unwrap().matches("a.txt"));
+    assert!(Pattern::with_style(r"a\b", PathStyle::Posix).unwrap().matches(r"a\b"));
+    assert!(!Pattern::with_style(r"a\b", PathStyle::Posix).unwrap().matches("a/b"));
+
+    // errors point into the original pattern
+    assert_eq!(Pattern::with_style(r"\\?\C:\a**", PathStyle::Windows).unwrap_err().char_span.start, 7);
+  }
+
+  #[test]
+  fn split_root() {
+    let split = |&: p: &str| {
+      let (root, rest) = PathStyle::Windows.split_root(p);
+      (root.unwrap_or(String::new()), rest.to_string())
+    };
+
+    assert_eq!(split(r"c:\x"), ("C:/".to_string(), "x".to_string()));
+    assert_eq!(split("c:x"), ("C:".to_string(), "x".to_string()));
+    assert_eq!(split(r"\x"), ("/".to_string(), "x".to_string()));
+    assert_eq!(split(r"\\?\c:\x"), ("C:/".to_string(), "x".to_string()));
+    assert_eq!(split(r"\\?\UNC\srv\shr\x"), ("//srv/shr/".to_string(), "x".to_string()));
+    assert_eq!(split(r"\\.\COM1"), ("//./COM1".to_string(), "".to_string()));
+    assert_eq!(split("x/y"), ("".to_string(), "x/y".to_string()));
+    assert_eq!(PathStyle::Posix.split_root("/x"), (Some("/".to_string()), "x"));
+    assert_eq!(PathStyle::Posix.split_root(r"c:\x"), (None, r"c:\x"));
   }
 
   #[test]
@@ -526,4 +1782,32 @@ mod test {
     // tests that / and \ are considered equivalent on windows
     assert!(Pattern::new("a/b").unwrap().matches_path(&Path::new("a/b")));
   }
+
+  #[test]
+  fn relations() {
+    let posix = |&: pattern: &str| Pattern::with_style(pattern, PathStyle::Posix).unwrap();
+
+    assert_eq!(posix("src/**/*.rs").is_subset_of(&posix("**/*.rs")), Ok(()));
+    assert_eq!(posix("lib[0-9].a").is_subset_of(&posix("lib?.a")), Ok(()));
+    assert_eq!(posix("*.txt").is_subset_of(&posix("*.txt")), Ok(()));
+
+    let (narrow, wide) = (posix("src/**/*.rs"), posix("**/*.rs"));
+    let witness = wide.is_subset_of(&narrow).unwrap_err();
+    assert!(wide.matches(witness.as_slice()) && !narrow.matches(witness.as_slice()));
+
+    let witness = posix("[!a]").is_subset_of(&posix("?")).err();
+    assert_eq!(witness, None);
+    let witness = posix("?").is_subset_of(&posix("[!a]")).unwrap_err();
+    assert!(witness.ends_with("a"));
+
+    assert_eq!(posix("a*").intersects(&posix("*b")), Some("ab".to_string()));
+    assert_eq!(posix("*.c").intersects(&posix("*.h")), None);
+    assert_eq!(posix("a/*").intersects(&posix("a/b/c")), None);
+    assert!(posix("a/**").intersects(&posix("*/b/c")).is_some());
+
+    let windows = |&: pattern: &str| Pattern::with_style(pattern, PathStyle::Windows).unwrap();
+    assert_eq!(windows(r"C:\Temp\*.TXT").is_subset_of(&windows(r"c:\**\*.txt")), Ok(()));
+    let witness = windows("[a-c]").intersects(&windows("B")).unwrap();
+    assert!(witness.as_slice() == "b" || witness.as_slice() == "B");
+  }
 }
@@ -0,0 +1,166 @@
+//! What every walker needs to know before it starts: where to begin, the
+//! pattern split into per-component selectors, and what to yield.
+
+use std::str;
+
+use pattern::{Pattern, PathStyle, Error};
+use entry::GlobEntry;
+use filesystem::FileSystem;
+use options::GlobOptions;
+use qualifiers;
+
+static WILDCARD: ::regex::Regex = regex!(r"[[*?]");
+
+pub enum Component {
+  /// a literal path component, to be looked up with `resolve`
+  Precise(String),
+  /// a path component with wildcards
+  Wildcard(Pattern),
+  /// `**`
+  Recursive,
+}
+
+// what the terminating selectors check before yielding an entry
+pub struct Filter {
+  // the pattern ended in a separator
+  pub is_dir: bool,
+  pub options: GlobOptions,
+}
+
+impl Filter {
+  pub fn accepts<F: FileSystem>(&self, fs: &F, entry: &GlobEntry) -> bool {
+    (!self.is_dir || entry.is_dir_in(fs)) && self.options.accepts_in(fs, entry)
+  }
+}
+
+pub struct Plan {
+  /// the pattern without its qualifiers or trailing separator, for matching
+  /// the paths found against as a whole
+  pub pattern: String,
+  pub scope: Path,
+  pub components: Vec<Component>,
+  pub filter: Filter,
+}
+
+impl Plan {
+  pub fn new(original: &str, options: &GlobOptions) -> Result<Plan, Error> {
+    let (pattern, options) = try!(qualifiers::parse(original, options));
+    let style = options.style;
+
+    let (root, trimmed) = style.split_root(pattern);
+    let scope = root.map(|root| volume_relative(Path::new(root))).unwrap_or_else(|| Path::new("."));
+
+    // errors point into the pattern as written, root and all
+    let components = try!(components(trimmed, style).map_err(|e| {
+      let offset = pattern.slice_to(pattern.len() - trimmed.len()).chars().count();
+      Error::new(e.kind, original, e.char_span.start + offset..e.char_span.end + offset)
+    }));
+    let is_dir = pattern.chars().next_back().map(|c| style.is_sep(c)) == Some(true);
+    let whole = if is_dir && !trimmed.is_empty() { pattern.slice_to(pattern.len() - 1) } else { pattern };
+
+    Ok(Plan {
+      pattern: whole.to_string(),
+      scope: scope,
+      components: components,
+      filter: Filter {
+        is_dir: is_dir,
+        options: options,
+      },
+    })
+  }
+}
+
+// a root like `C:` is relative to the current directory on that drive
+#[cfg(windows)]
+fn volume_relative(root: Path) -> Path {
+  use std::os;
+  use std::path::windows;
+
+  if windows::is_vol_relative(&root) {
+    os::getcwd().unwrap().join(root)
+  } else {
+    root
+  }
+}
+#[cfg(not(windows))]
+fn volume_relative(root: Path) -> Path { root }
+
+/// Whether a listed entry's file name is the literal component `name`.
+/// Case doesn't matter in styles where it doesn't for patterns.
+pub fn is_named(name: &str, filename: &[u8], style: PathStyle) -> bool {
+  if name.as_bytes() == filename {
+    return true;
+  }
+
+  let fold = |&: s: &str| -> String { s.chars().map(|c| c.to_lowercase()).collect() };
+
+  match str::from_utf8(filename) {
+    Ok(filename) if !style.is_case_sensitive() => fold(name) == fold(filename),
+    _ => false,
+  }
+}
+
+/// The entry a literal component names in `dir`, if there is one.
+///
+/// That's the joined path when it exists. Failing that, where case doesn't
+/// matter, it's the first entry of the listing named the same but for case,
+/// as a case-sensitive filesystem can spell it differently.
+pub fn resolve<F: FileSystem>(fs: &F, dir: &GlobEntry, name: &str, style: PathStyle)
+                              -> Option<GlobEntry> {
+  let depth = dir.depth() + 1;
+  let joined = GlobEntry::new(dir.path().join(name), depth);
+
+  if joined.exists_in(fs) {
+    return Some(joined);
+  }
+
+  if style.is_case_sensitive() {
+    return None;
+  }
+
+  let ents = match fs.read_dir(dir.path()) {
+    Ok(ents) => ents,
+    Err(..) => return None,
+  };
+
+  for (path, kind) in ents {
+    if path.filename().map_or(false, |filename| is_named(name, filename, style)) {
+      return Some(GlobEntry::with_kind(path, depth, kind));
+    }
+  }
+
+  None
+}
+
+pub fn components(pattern: &str, style: PathStyle) -> Result<Vec<Component>, Error> {
+  // compile pattern to make sure there are no immediate errors
+  let _compiled = try!(Pattern::with_style(pattern, style));
+  // TODO: should this be split on r"[^\]{SEP}"
+
+  let mut components = Vec::new();
+  let mut was_recursive = false;
+
+  // collapse consecutive recursive patterns
+  for pattern in pattern.split(|&: c: char| style.is_sep(c)) {
+    if pattern == "**" {
+      if was_recursive {
+        continue;
+      } else {
+        was_recursive = true;
+      }
+
+      components.push(Component::Recursive);
+      continue;
+    }
+
+    was_recursive = false;
+
+    if WILDCARD.is_match(pattern) {
+      components.push(Component::Wildcard(try!(Pattern::with_style(pattern, style))));
+    } else {
+      components.push(Component::Precise(pattern.to_string()));
+    }
+  }
+
+  Ok(components)
+}
@@ -0,0 +1,437 @@
+//! zsh-style glob qualifiers, e.g. `**/*(.)` or `*(om[1,5])`.
+//!
+//! A pattern ending in a parenthesized group has that group parsed into
+//! `GlobOptions`. Qualifiers written next to each other must all hold, as
+//! in zsh; the `,` and `^` operators aren't supported. A literal trailing
+//! parenthesis can be written as `[)]`.
+//!
+//! * `.` plain files, `/` directories, `@` symlinks, `=` sockets,
+//!   `p` FIFOs, `%` devices (`%b` block, `%c` character)
+//! * `*` executable plain files
+//! * `r`, `w`, `x` readable, writable, executable by the owner
+//! * `L[k|m|g|p][+|-]n` size in bytes, or in the given unit; the unit may
+//!   also follow the number, as in `L+10m`
+//! * `m[M|w|h|m|s][+|-]n` modified n days (or the given unit) ago
+//! * `u<n>`, `g<n>` owned by the numeric uid or gid
+//! * `o<c>`, `O<c>` sort by name (`n`), size (`L`) or modification time
+//!   (`m`), ascending or descending; `om` puts the newest first
+//! * `[n]`, `[a,b]` keep only the given 1-based range of matches
+//! * `N` is accepted for compatibility; an empty result is never an error
+
+use std::isize;
+use std::ops::Range;
+use time;
+
+use entry::FileKind;
+use options::{GlobOptions, FileTypes, SortKey};
+use pattern::{Error, ErrorKind};
+
+/// Splits a trailing qualifier group off `pattern`.
+///
+/// Returns the bare pattern along with `options` refined by the qualifiers.
+/// Patterns without a trailing group are returned unchanged.
+pub fn parse<'a>(pattern: &'a str, options: &GlobOptions)
+                 -> Result<(&'a str, GlobOptions), Error> {
+  let now = time::get_time();
+  let now = now.sec as u64 * 1000 + now.nsec as u64 / 1_000_000;
+
+  parse_at(pattern, options, now)
+}
+
+fn parse_at<'a>(pattern: &'a str, options: &GlobOptions, now: u64)
+                -> Result<(&'a str, GlobOptions), Error> {
+  if !pattern.ends_with(")") {
+    return Ok((pattern, options.clone()));
+  }
+
+  let open = match pattern.rfind('(') {
+    Some(open) => open,
+    None => return Ok((pattern, options.clone())),
+  };
+
+  let bare = pattern.slice_to(open);
+  let group = pattern.slice(open + 1, pattern.len() - 1);
+
+  let mut parser = Parser {
+    pattern: pattern,
+    chars: group.chars().collect(),
+    pos: 0,
+    offset: bare.chars().count() + 1,
+    options: options.clone(),
+    now: now,
+  };
+
+  try!(parser.parse());
+
+  Ok((bare, parser.options))
+}
+
+struct Parser<'a> {
+  pattern: &'a str,
+  chars: Vec<char>,
+  pos: usize,
+  // position of the group within the whole pattern, for errors
+  offset: usize,
+  options: GlobOptions,
+  now: u64,
+}
+
+impl<'a> Parser<'a> {
+  // an error covering the group from `start` up to where parsing got,
+  // and at least one character
+  fn error(&self, kind: ErrorKind, start: usize) -> Error {
+    let end = if self.pos > start { self.pos } else { start + 1 };
+    Error::new(kind, self.pattern, self.offset + start..self.offset + end)
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).map(|c| *c)
+  }
+
+  fn bump(&mut self) -> Option<char> {
+    let c = self.peek();
+    if c.is_some() {
+      self.pos += 1;
+    }
+    c
+  }
+
+  fn restrict(&mut self, file_types: FileTypes) {
+    self.options.file_types = self.options.file_types.intersection(file_types);
+  }
+
+  fn parse(&mut self) -> Result<(), Error> {
+    while let Some(c) = self.bump() {
+      let start = self.pos - 1;
+
+      match c {
+        '.' => self.restrict(FileTypes::files()),
+        '/' => self.restrict(FileTypes::directories()),
+        '@' => self.restrict(FileTypes::symlinks()),
+        '=' => self.restrict(FileTypes::of(FileKind::Socket)),
+        'p' => self.restrict(FileTypes::of(FileKind::Fifo)),
+        '%' => {
+          let devices = match self.peek() {
+            Some('b') => { self.pos += 1; FileTypes::of(FileKind::BlockDevice) }
+            Some('c') => { self.pos += 1; FileTypes::of(FileKind::CharDevice) }
+            _ => FileTypes::of(FileKind::BlockDevice)
+                   .union(FileTypes::of(FileKind::CharDevice)),
+          };
+          self.restrict(devices);
+        }
+        '*' => {
+          self.restrict(FileTypes::files());
+          self.options.executable = true;
+        }
+        'r' => self.add_mode(0o400),
+        'w' => self.add_mode(0o200),
+        'x' => self.add_mode(0o100),
+        'L' => try!(self.size()),
+        'm' => try!(self.modified()),
+        'u' => {
+          let id = try!(self.number());
+          self.options.uid = Some(id);
+        }
+        'g' => {
+          let id = try!(self.number());
+          self.options.gid = Some(id);
+        }
+        'o' | 'O' => {
+          let key = match self.bump() {
+            Some('n') => SortKey::Name,
+            Some('L') => SortKey::Size,
+            Some('m') => SortKey::Modified,
+            _ => return Err(self.error(ErrorKind::UnknownSortOrder, start + 1)),
+          };
+          self.options = self.options.clone().sort_by(key, c == 'O');
+        }
+        '[' => try!(self.slice(start)),
+        'N' => (),
+        '^' | ',' | '-' => return Err(self.error(ErrorKind::UnsupportedOperator, start)),
+        _ => return Err(self.error(ErrorKind::UnknownQualifier, start)),
+      }
+    }
+
+    Ok(())
+  }
+
+  fn add_mode(&mut self, bits: u32) {
+    let mode = self.options.mode.unwrap_or(0);
+    self.options.mode = Some(mode | bits);
+  }
+
+  fn sign(&mut self) -> Option<char> {
+    match self.peek() {
+      Some(c) if c == '+' || c == '-' => {
+        self.pos += 1;
+        Some(c)
+      }
+      _ => None,
+    }
+  }
+
+  fn number(&mut self) -> Result<u64, Error> {
+    let start = self.pos;
+
+    while self.peek().map_or(false, |c| c.is_digit(10)) {
+      self.pos += 1;
+    }
+
+    if start == self.pos {
+      return Err(self.error(ErrorKind::ExpectedNumber, start));
+    }
+
+    let digits: String = self.chars.slice(start, self.pos).iter().map(|c| *c).collect();
+
+    digits.parse::<u64>().ok_or_else(|| self.error(ErrorKind::NumberTooLarge, start))
+  }
+
+  // the sign, the number, and where its digits are in the group
+  fn signed_number(&mut self) -> Result<(Option<char>, u64, Range<usize>), Error> {
+    let sign = self.sign();
+    let start = self.pos;
+    let n = try!(self.number());
+    Ok((sign, n, start..self.pos))
+  }
+
+  // `n * unit + extra`, or an error over `digits` when that doesn't fit
+  fn scale(&self, n: u64, unit: u64, extra: u64, digits: &Range<usize>) -> Result<u64, Error> {
+    n.checked_mul(unit).and_then(|n| n.checked_add(extra)).ok_or_else(|| {
+      Error::new(ErrorKind::NumberTooLarge, self.pattern,
+                 self.offset + digits.start..self.offset + digits.end)
+    })
+  }
+
+  fn unit(&mut self, units: &[(char, u64)], default: u64) -> u64 {
+    match self.peek() {
+      Some(c) => match units.iter().find(|&&(u, _)| u == c) {
+        Some(&(_, unit)) => {
+          self.pos += 1;
+          unit
+        }
+        None => default,
+      },
+      None => default,
+    }
+  }
+
+  fn size(&mut self) -> Result<(), Error> {
+    let units = [('k', 1 << 10), ('m', 1 << 20), ('g', 1 << 30), ('p', 512)];
+    let unit = self.unit(&units, 0);
+    let (sign, n, digits) = try!(self.signed_number());
+    let unit = if unit == 0 { self.unit(&units, 1) } else { unit };
+
+    // like zsh, sizes are rounded up to whole units
+    match sign {
+      Some('+') => self.options.larger_than = Some(try!(self.scale(n, unit, 0, &digits))),
+      Some('-') => {
+        self.options.smaller_than = Some(try!(self.scale(n.saturating_sub(1), unit, 1, &digits)));
+      }
+      _ => {
+        if n > 0 {
+          self.options.larger_than = Some(try!(self.scale(n - 1, unit, 0, &digits)));
+        }
+        self.options.smaller_than = Some(try!(self.scale(n, unit, 1, &digits)));
+      }
+    }
+
+    Ok(())
+  }
+
+  fn modified(&mut self) -> Result<(), Error> {
+    let second = 1000;
+    let day = 24 * 60 * 60 * second;
+    let unit = self.unit(&[('M', 30 * day), ('w', 7 * day), ('d', day),
+                           ('h', 60 * 60 * second), ('m', 60 * second), ('s', second)],
+                         day);
+
+    let (sign, n, digits) = try!(self.signed_number());
+
+    // age is rounded down to whole units, so it's at least `n` units and
+    // less than `n + 1`
+    let newest = self.now.saturating_sub(try!(self.scale(n, unit, 0, &digits)));
+    let oldest = self.now.saturating_sub(try!(self.scale(n, unit, unit, &digits)));
+
+    match sign {
+      Some('+') => self.options.modified_before = Some(oldest),
+      Some('-') => self.options.modified_since = Some(newest),
+      _ => {
+        self.options.modified_since = Some(oldest);
+        self.options.modified_before = Some(newest);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn slice(&mut self, start: usize) -> Result<(), Error> {
+    let from = try!(self.index());
+
+    let to =
+      if self.peek() == Some(',') {
+        self.pos += 1;
+        try!(self.index())
+      } else {
+        from
+      };
+
+    if self.bump() != Some(']') {
+      return Err(self.error(ErrorKind::UnclosedQualifierRange, start));
+    }
+
+    self.options = self.options.clone().slice(from, to);
+
+    Ok(())
+  }
+
+  fn index(&mut self) -> Result<isize, Error> {
+    let start = self.pos;
+
+    let negative = self.peek() == Some('-');
+    if negative {
+      self.pos += 1;
+    }
+
+    let digits = self.pos;
+    let n = try!(self.number());
+
+    if n == 0 {
+      return Err(self.error(ErrorKind::ZeroIndex, start));
+    }
+
+    if n > isize::MAX as u64 {
+      return Err(self.error(ErrorKind::NumberTooLarge, digits));
+    }
+
+    Ok(if negative { -(n as isize) } else { n as isize })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::parse_at;
+  use entry::FileKind;
+  use options::{GlobOptions, FileTypes, SortKey};
+  use pattern::ErrorKind;
+
+  fn parse(pattern: &str) -> (&str, GlobOptions) {
+    parse_at(pattern, &GlobOptions::new(), 100_000_000).unwrap()
+  }
+
+  #[test]
+  fn no_qualifiers() {
+    let (bare, options) = parse("**/*.rs");
+    assert_eq!(bare, "**/*.rs");
+    assert_eq!(options.file_types, FileTypes::all());
+
+    // no opening parenthesis, so the `)` is literal
+    assert_eq!(parse("a)").0, "a)");
+  }
+
+  #[test]
+  fn file_types() {
+    let (bare, options) = parse("**/*(.)");
+    assert_eq!(bare, "**/*");
+    assert_eq!(options.file_types, FileTypes::files());
+
+    assert_eq!(parse("*(/)").1.file_types, FileTypes::directories());
+    assert_eq!(parse("*(@)").1.file_types, FileTypes::symlinks());
+    assert_eq!(parse("*(%b)").1.file_types, FileTypes::of(FileKind::BlockDevice));
+
+    let (_, options) = parse("*(*)");
+    assert_eq!(options.file_types, FileTypes::files());
+    assert!(options.executable);
+
+    // juxtaposed qualifiers must all hold
+    assert_eq!(parse("*(./)").1.file_types, FileTypes::empty());
+  }
+
+  #[test]
+  fn sort_and_slice() {
+    let (bare, options) = parse("*(om[1,5])");
+    assert_eq!(bare, "*");
+    let sort = options.sort.unwrap();
+    assert_eq!(sort.key, SortKey::Modified);
+    assert!(!sort.reverse);
+    assert_eq!(options.slice, Some((1, 5)));
+
+    let (_, options) = parse("*(OL[-1])");
+    assert!(options.sort.unwrap().reverse);
+    assert_eq!(options.slice, Some((-1, -1)));
+
+    let (_, options) = parse("*(N)");
+    assert!(options.sort.is_none());
+  }
+
+  #[test]
+  fn metadata() {
+    let (_, options) = parse("*(.L+10m)");
+    assert_eq!(options.file_types, FileTypes::files());
+    assert_eq!(options.larger_than, Some(10 * 1024 * 1024));
+
+    let (_, options) = parse("*(Lk-2)");
+    assert_eq!(options.smaller_than, Some(1024 + 1));
+
+    let (_, options) = parse("*(mh-1)");
+    assert_eq!(options.modified_since, Some(100_000_000 - 60 * 60 * 1000));
+
+    let (_, options) = parse("*(u1000g100)");
+    assert_eq!(options.uid, Some(1000));
+    assert_eq!(options.gid, Some(100));
+
+    assert_eq!(parse("*(rw)").1.mode, Some(0o600));
+  }
+
+  #[test]
+  fn errors() {
+    let err = parse_at("a/*(.q)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::UnknownQualifier);
+    assert_eq!(err.char_span, 5..6);
+    assert_eq!(err.pattern.as_slice(), "a/*(.q)");
+
+    let err = parse_at("*(oz)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::UnknownSortOrder);
+    assert_eq!(err.char_span, 3..4);
+
+    let err = parse_at("*(L)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::ExpectedNumber);
+    assert_eq!(err.char_span, 3..4);
+
+    let err = parse_at("*([1,2)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::UnclosedQualifierRange);
+    assert_eq!(err.char_span, 2..6);
+
+    let err = parse_at("*(^.)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::UnsupportedOperator);
+    assert_eq!(err.char_span, 2..3);
+
+    let err = parse_at("é(m99999999999999999999)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NumberTooLarge);
+    assert_eq!(err.char_span, 3..23);
+    assert_eq!(err.span, 4..24);
+  }
+
+  #[test]
+  fn overflow() {
+    let err = parse_at("*(mM9999999999999)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NumberTooLarge);
+    assert_eq!(err.char_span, 4..17);
+
+    let err = parse_at("*(L+18446744073709551615k)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NumberTooLarge);
+    assert_eq!(err.char_span, 4..24);
+
+    let err = parse_at("*(L18446744073709551615)", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NumberTooLarge);
+
+    let err = parse_at("*([18446744073709551615])", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NumberTooLarge);
+    assert_eq!(err.char_span, 3..23);
+
+    let err = parse_at("*([1,-9223372036854775808])", &GlobOptions::new(), 0).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NumberTooLarge);
+    assert_eq!(err.char_span, 6..25);
+
+    assert!(parse_at("*(L-18446744073709551615)", &GlobOptions::new(), 0).is_ok());
+  }
+}
@@ -0,0 +1,247 @@
+//! Directory listing that keeps the file type reported by the platform.
+//!
+//! `std::io::fs::readdir` only hands back paths, so every consumer ends up
+//! calling `stat` on each entry just to learn whether it's a directory. On
+//! platforms whose `readdir(3)` fills in `d_type` we can avoid that, though
+//! only Linux, macOS, iOS, FreeBSD and DragonFly are done so far.
+//!
+//! It also reads the whole listing up front, which for a directory with
+//! millions of entries means a large allocation before the first match.
+//! `ReadDir` instead yields entries as the platform returns them.
+
+use std::io::IoResult;
+
+use entry::FileKind;
+
+pub use self::imp::ReadDir;
+
+/// Lists the entries of `path`, excluding `.` and `..`.
+///
+/// Each entry is paired with its file type when the platform reported it,
+/// or `None` when the caller has to `lstat` to find out.
+pub fn read_dir(path: &Path) -> IoResult<ReadDir> {
+  imp::read_dir(path)
+}
+
+// the platforms whose `dirent` layout is known below
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "ios",
+          target_os = "freebsd", target_os = "dragonfly"))]
+mod imp {
+  use libc::{c_char, c_int};
+  use std::ffi::{CString, c_str_to_bytes};
+  use std::io::{IoError, IoResult};
+
+  use entry::FileKind;
+
+  #[allow(non_camel_case_types)]
+  enum DIR {}
+
+  // glibc's `dirent` follows `_FILE_OFFSET_BITS`, so its inode and offset
+  // are 32 bits on 32-bit targets built without large file support;
+  // `dirent64` is the same everywhere
+  #[cfg(target_os = "linux")]
+  #[repr(C)]
+  struct dirent {
+    d_ino: u64,
+    d_off: i64,
+    d_reclen: u16,
+    d_type: u8,
+    d_name: [c_char; 256],
+  }
+
+  #[cfg(any(target_os = "macos", target_os = "ios"))]
+  #[repr(C)]
+  struct dirent {
+    d_ino: u64,
+    d_seekoff: u64,
+    d_reclen: u16,
+    d_namlen: u16,
+    d_type: u8,
+    d_name: [c_char; 1024],
+  }
+
+  // FreeBSD 12 widened the inode and added the offset; as the libc crate
+  // does, building with `--cfg freebsd11` selects the older layout
+  #[cfg(all(target_os = "freebsd", not(freebsd11)))]
+  #[repr(C)]
+  struct dirent {
+    d_fileno: u64,
+    d_off: i64,
+    d_reclen: u16,
+    d_type: u8,
+    d_pad0: u8,
+    d_namlen: u16,
+    d_pad1: u16,
+    d_name: [c_char; 256],
+  }
+
+  #[cfg(all(target_os = "freebsd", freebsd11))]
+  #[repr(C)]
+  struct dirent {
+    d_fileno: u32,
+    d_reclen: u16,
+    d_type: u8,
+    d_namlen: u8,
+    d_name: [c_char; 256],
+  }
+
+  #[cfg(target_os = "dragonfly")]
+  #[repr(C)]
+  struct dirent {
+    d_fileno: u64,
+    d_namlen: u16,
+    d_type: u8,
+    d_unused1: u8,
+    d_unused2: u32,
+    d_name: [c_char; 256],
+  }
+
+  const DT_FIFO: u8 = 1;
+  const DT_CHR: u8 = 2;
+  const DT_DIR: u8 = 4;
+  const DT_BLK: u8 = 6;
+  const DT_REG: u8 = 8;
+  const DT_LNK: u8 = 10;
+  const DT_SOCK: u8 = 12;
+
+  extern {
+    // macOS on Intel keeps the old 32-bit inode `dirent` under the plain
+    // names
+    #[cfg_attr(all(target_os = "macos", target_arch = "x86_64"), link_name = "opendir$INODE64")]
+    #[cfg_attr(all(target_os = "macos", target_arch = "x86"),
+               link_name = "opendir$INODE64$UNIX2003")]
+    fn opendir(name: *const c_char) -> *mut DIR;
+    #[cfg_attr(target_os = "linux", link_name = "readdir64")]
+    #[cfg_attr(all(target_os = "macos", any(target_arch = "x86", target_arch = "x86_64")),
+               link_name = "readdir$INODE64")]
+    fn readdir(dirp: *mut DIR) -> *mut dirent;
+    fn closedir(dirp: *mut DIR) -> c_int;
+  }
+
+  fn kind_of(d_type: u8) -> Option<FileKind> {
+    match d_type {
+      DT_REG => Some(FileKind::File),
+      DT_DIR => Some(FileKind::Directory),
+      DT_LNK => Some(FileKind::Symlink),
+      DT_FIFO => Some(FileKind::Fifo),
+      DT_SOCK => Some(FileKind::Socket),
+      DT_BLK => Some(FileKind::BlockDevice),
+      DT_CHR => Some(FileKind::CharDevice),
+      // DT_UNKNOWN: some filesystems never fill in d_type
+      _ => None,
+    }
+  }
+
+  pub struct ReadDir {
+    dir: *mut DIR,
+    path: Path,
+  }
+
+  // the handle is only ever used by whoever owns the `ReadDir`
+  unsafe impl Send for ReadDir {}
+
+  impl Iterator for ReadDir {
+    type Item = (Path, Option<FileKind>);
+
+    fn next(&mut self) -> Option<(Path, Option<FileKind>)> {
+      loop {
+        // a read error ends the listing, same as reaching its end
+        let ent = unsafe { readdir(self.dir) };
+
+        if ent.is_null() {
+          return None;
+        }
+
+        let (bytes, d_type) = unsafe {
+          let name = (*ent).d_name.as_ptr();
+          (c_str_to_bytes(&name).to_vec(), (*ent).d_type)
+        };
+
+        if bytes == b"." || bytes == b".." {
+          continue;
+        }
+
+        return Some((self.path.join(bytes.as_slice()), kind_of(d_type)));
+      }
+    }
+  }
+
+  impl Drop for ReadDir {
+    fn drop(&mut self) {
+      unsafe { closedir(self.dir); }
+    }
+  }
+
+  pub fn read_dir(path: &Path) -> IoResult<ReadDir> {
+    let name = CString::from_slice(path.as_vec());
+    let dir = unsafe { opendir(name.as_ptr()) };
+
+    if dir.is_null() {
+      return Err(IoError::last_error());
+    }
+
+    Ok(ReadDir {
+      dir: dir,
+      path: path.clone(),
+    })
+  }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios",
+              target_os = "freebsd", target_os = "dragonfly")))]
+mod imp {
+  use std::io::IoResult;
+  use std::io::fs;
+  use std::vec;
+
+  use entry::FileKind;
+
+  // without a known `dirent` layout, std is all there is, and it buffers
+  pub struct ReadDir {
+    paths: vec::IntoIter<Path>,
+  }
+
+  impl Iterator for ReadDir {
+    type Item = (Path, Option<FileKind>);
+
+    fn next(&mut self) -> Option<(Path, Option<FileKind>)> {
+      self.paths.next().map(|p| (p, None))
+    }
+  }
+
+  pub fn read_dir(path: &Path) -> IoResult<ReadDir> {
+    fs::readdir(path).map(|paths| ReadDir { paths: paths.into_iter() })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::{self, TempDir};
+  use std::io::fs;
+
+  use super::read_dir;
+  use entry::FileKind;
+
+  #[test]
+  fn lists_entries() {
+    let root = TempDir::new("glob-readdir").unwrap();
+    fs::mkdir(&root.path().join("dir"), io::USER_RWX).unwrap();
+    io::File::create(&root.path().join("file")).unwrap();
+
+    let mut entries: Vec<_> = read_dir(root.path()).unwrap().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].0, root.path().join("dir"));
+    assert_eq!(entries[1].0, root.path().join("file"));
+
+    // d_type may legitimately be unknown, but must never be wrong
+    assert!(entries[0].1.map_or(true, |k| k == FileKind::Directory));
+    assert!(entries[1].1.map_or(true, |k| k == FileKind::File));
+  }
+
+  #[test]
+  fn missing_directory() {
+    assert!(read_dir(&Path::new("/this/does/not/exist")).is_err());
+  }
+}
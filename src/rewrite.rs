@@ -0,0 +1,201 @@
+//! Rewriting matched paths through templates, as `mmv` does.
+//!
+//! In a template, `#n` stands for what the pattern's nth wildcard matched,
+//! counting `*`, `?`, `**` and classes from 1, `#{name}` for the wildcard
+//! written as `{name:...}`, and `##` for a `#`. With the pattern
+//! `src/**/*.c`, the template `build/#1/#2.o` takes `src/net/tcp.c` to
+//! `build/net/tcp.o`.
+//!
+//! ```ignore
+//! for (entry, dest) in glob("src/**/*.c").unwrap().rewrite("build/#1/#2.o").unwrap() {
+//!   println!("{} -> {}", entry.path().display(), dest.unwrap().display());
+//! }
+//! ```
+
+use std::str;
+
+use pattern::{Pattern, Captures, Error, ErrorKind};
+
+#[derive(Clone, PartialEq, Eq, Show)]
+enum Piece {
+  Text(String),
+  // the index of a wildcard
+  Capture(usize),
+}
+
+/// A template checked against the wildcards of a pattern.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Template {
+  source: String,
+  pieces: Vec<Piece>,
+}
+
+impl Template {
+  /// Parses `template`, failing if it refers to a wildcard `pattern`
+  /// doesn't have.
+  pub fn new(template: &str, pattern: &Pattern) -> Result<Template, Error> {
+    let chars: Vec<char> = template.chars().collect();
+    let names = pattern.capture_names();
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+      if chars[i] != '#' {
+        text.push(chars[i]);
+        i += 1;
+        continue;
+      }
+
+      let (index, next) = match chars.get(i + 1).map(|c| *c) {
+        Some('#') => {
+          text.push('#');
+          i += 2;
+          continue;
+        }
+        Some(c) if c.is_digit(10) => {
+          let end = range(i + 1, chars.len()).find(|&j| !chars[j].is_digit(10)).unwrap_or(chars.len());
+          let number: String = chars.slice(i + 1, end).iter().map(|c| *c).collect();
+
+          match number.parse::<usize>() {
+            Some(n) if n > 0 && n <= names.len() => (n - 1, end),
+            _ => return Err(Error::new(ErrorKind::NoSuchCapture, template, i..end)),
+          }
+        }
+        Some('{') => {
+          let end = match chars.slice_from(i + 2).position_elem(&'}') {
+            Some(j) => i + 2 + j,
+            None => return Err(Error::new(ErrorKind::InvalidTemplate, template, i..chars.len())),
+          };
+          let name: String = chars.slice(i + 2, end).iter().map(|c| *c).collect();
+
+          match names.iter().position(|n| n.as_ref() == Some(&name)) {
+            Some(index) => (index, end + 1),
+            None => return Err(Error::new(ErrorKind::NoSuchCapture, template, i..end + 1)),
+          }
+        }
+        _ => return Err(Error::new(ErrorKind::InvalidTemplate, template, i..i + 1)),
+      };
+
+      if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+        text = String::new();
+      }
+
+      pieces.push(Piece::Capture(index));
+      i = next;
+    }
+
+    if !text.is_empty() {
+      pieces.push(Piece::Text(text));
+    }
+
+    Ok(Template {
+      source: template.to_string(),
+      pieces: pieces,
+    })
+  }
+
+  pub fn as_str(&self) -> &str {
+    self.source.as_slice()
+  }
+
+  /// The template with the captures filled in. They should come from the
+  /// pattern the template was checked against.
+  ///
+  /// A capture that matched nothing takes a separator next to it along, so
+  /// `build/#1/#2.o` gives `build/main.o` rather than `build//main.o` when
+  /// `#1` is an empty `**`.
+  pub fn fill(&self, captures: &Captures) -> String {
+    let mut filled = String::new();
+    // an empty capture just went by, after a separator or at the start
+    let mut dangling = false;
+
+    for piece in self.pieces.iter() {
+      match *piece {
+        Piece::Text(ref text) => {
+          let text = text.as_slice();
+
+          if dangling && text.starts_with("/") {
+            filled.push_str(text.slice_from(1));
+          } else {
+            filled.push_str(text);
+          }
+
+          dangling = false;
+        }
+        Piece::Capture(i) => {
+          let capture = captures.get(i).unwrap_or("");
+
+          if capture.is_empty() {
+            dangling = dangling || filled.is_empty() || filled.ends_with("/");
+          } else {
+            filled.push_str(capture);
+            dangling = false;
+          }
+        }
+      }
+    }
+
+    filled
+  }
+}
+
+impl Pattern {
+  /// `template` filled in from what the pattern matched in `path`, if it
+  /// matches.
+  pub fn rewrite(&self, path: &str, template: &Template) -> Option<String> {
+    self.captures(path).map(|captures| template.fill(&captures))
+  }
+
+  /// Like `rewrite`, for paths that may not be UTF-8; those that aren't
+  /// aren't rewritten.
+  pub fn rewrite_path(&self, path: &Path, template: &Template) -> Option<Path> {
+    str::from_utf8(path.as_vec()).ok()
+      .and_then(|path| self.rewrite(path, template))
+      .map(|rewritten| Path::new(rewritten))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Template;
+  use pattern::ErrorKind;
+  use testing::pattern;
+
+  #[test]
+  fn rewrite() {
+    let pat = pattern("src/**/*.c");
+    let template = Template::new("build/#1/#2.o", &pat).unwrap();
+
+    assert_eq!(pat.rewrite("src/net/tcp.c", &template), Some("build/net/tcp.o".to_string()));
+    assert_eq!(pat.rewrite("src/a/b/c.c", &template), Some("build/a/b/c.o".to_string()));
+    assert_eq!(pat.rewrite("src/main.h", &template), None);
+    assert_eq!(pat.rewrite("src/main.c", &template), Some("build/main.o".to_string()));
+    assert_eq!(pat.rewrite_path(&Path::new("src/main.c"), &template), Some(Path::new("build/main.o")));
+
+    let pat = pattern("{name:*}.tar.{ext:[gx]}z");
+    let template = Template::new("#{name}-###2.#{ext}z", &pat).unwrap();
+    assert_eq!(pat.rewrite("glob.tar.xz", &template), Some("glob-#x.xz".to_string()));
+
+    let pat = pattern("**/*.c");
+    let template = Template::new("#1/#2.o", &pat).unwrap();
+    assert_eq!(pat.rewrite("main.c", &template), Some("main.o".to_string()));
+  }
+
+  #[test]
+  fn invalid_templates() {
+    let pat = pattern("src/**/*.c");
+
+    let err = Template::new("build/#3.o", &pat).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::NoSuchCapture);
+    assert_eq!(err.char_span, 6..8);
+    assert_eq!(err.pattern.as_slice(), "build/#3.o");
+
+    assert_eq!(Template::new("#0", &pat).unwrap_err().kind, ErrorKind::NoSuchCapture);
+    assert_eq!(Template::new("#{stem}", &pat).unwrap_err().char_span, 0..7);
+    assert_eq!(Template::new("a#b", &pat).unwrap_err().kind, ErrorKind::InvalidTemplate);
+    assert_eq!(Template::new("#{x", &pat).unwrap_err().kind, ErrorKind::InvalidTemplate);
+    assert!(Template::new("plain", &pat).is_ok());
+  }
+}
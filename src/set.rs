@@ -0,0 +1,202 @@
+//! Choosing the most specific of several patterns matching a path, as
+//! `CODEOWNERS` files and editor configs do.
+//!
+//! Patterns are ranked by `Pattern::specificity`, which compares, in turn:
+//!
+//! 1. literal characters other than separators: more is more specific;
+//! 2. components, not counting empty ones: more is more specific;
+//! 3. `**`: fewer is more specific;
+//! 4. `*`: fewer is more specific;
+//! 5. `?`: fewer is more specific;
+//! 6. character classes: fewer is more specific;
+//! 7. the pattern's text: the one sorting first is more specific.
+//!
+//! The last makes the ranking total: patterns only rank the same if they're
+//! written the same. So `src/main.rs` beats `src/*.rs`, which beats both
+//! `*.rs` and `src/**`, and `src/**` beats `*.rs` by having more
+//! components.
+//!
+//! ```ignore
+//! let owners: PatternSet = ["**", "*.rs", "src/**", "src/*.rs"].iter()
+//!   .map(|p| Pattern::new(*p).unwrap())
+//!   .collect();
+//!
+//! assert_eq!(owners.most_specific("src/lib.rs"), Some(3));
+//! ```
+
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+
+use ast::Component;
+use pattern::{Pattern, Token};
+
+/// Where a pattern ranks; greater is more specific.
+#[derive(Clone, PartialEq, Eq, Show)]
+pub struct Specificity {
+  pub literals: usize,
+  pub components: usize,
+  pub recursive: usize,
+  pub sequences: usize,
+  pub any_chars: usize,
+  pub classes: usize,
+  pub pattern: String,
+}
+
+impl Ord for Specificity {
+  fn cmp(&self, other: &Specificity) -> Ordering {
+    // the counts where fewer is more specific, and the text, come from the
+    // other side
+    let key = |&: a: &Specificity, b: &Specificity| {
+      (a.literals, a.components, b.recursive, b.sequences, b.any_chars, b.classes, b.pattern.as_slice())
+    };
+
+    key(self, other).cmp(&key(other, self))
+  }
+}
+
+impl PartialOrd for Specificity {
+  fn partial_cmp(&self, other: &Specificity) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Pattern {
+  /// The pattern's rank against others; see the `set` module.
+  pub fn specificity(&self) -> Specificity {
+    let mut specificity = Specificity {
+      literals: 0,
+      components: 0,
+      recursive: 0,
+      sequences: 0,
+      any_chars: 0,
+      classes: 0,
+      pattern: self.as_str().to_string(),
+    };
+
+    for token in Pattern::diagnose(self.as_str(), self.style()).tokens.iter() {
+      match *token {
+        Token::Char('/') => {}
+        Token::Char(..) => specificity.literals += 1,
+        Token::AnyChar => specificity.any_chars += 1,
+        Token::AnySequence => specificity.sequences += 1,
+        Token::AnyRecursiveSequence => specificity.recursive += 1,
+        Token::AnyWithin(..) | Token::AnyExcept(..) => specificity.classes += 1,
+      }
+    }
+
+    specificity.components = self.ast().components().iter().filter(|component| {
+      match **component {
+        Component::Parts(ref parts) => !parts.is_empty(),
+        Component::Recursive => true,
+      }
+    }).count();
+
+    specificity
+  }
+}
+
+/// Patterns to match paths against together.
+pub struct PatternSet {
+  patterns: Vec<Pattern>,
+  specificities: Vec<Specificity>,
+}
+
+impl PatternSet {
+  pub fn new() -> PatternSet {
+    PatternSet {
+      patterns: Vec::new(),
+      specificities: Vec::new(),
+    }
+  }
+
+  /// Adds `pattern`, returning its index.
+  pub fn add(&mut self, pattern: Pattern) -> usize {
+    self.specificities.push(pattern.specificity());
+    self.patterns.push(pattern);
+    self.patterns.len() - 1
+  }
+
+  pub fn get(&self, i: usize) -> Option<&Pattern> {
+    self.patterns.get(i)
+  }
+
+  pub fn len(&self) -> usize {
+    self.patterns.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.patterns.is_empty()
+  }
+
+  /// The indices of the patterns matching `path`, in ascending order.
+  pub fn matches(&self, path: &str) -> Vec<usize> {
+    range(0, self.patterns.len()).filter(|&i| self.patterns[i].matches(path)).collect()
+  }
+
+  /// The index of the most specific pattern matching `path`. Of patterns
+  /// written the same, it's the first added.
+  pub fn most_specific(&self, path: &str) -> Option<usize> {
+    let mut best: Option<usize> = None;
+
+    for i in self.matches(path).into_iter() {
+      best = match best {
+        Some(b) if self.specificities[b] >= self.specificities[i] => Some(b),
+        _ => Some(i),
+      };
+    }
+
+    best
+  }
+}
+
+impl FromIterator<Pattern> for PatternSet {
+  fn from_iter<I: Iterator<Item=Pattern>>(patterns: I) -> PatternSet {
+    let mut set = PatternSet::new();
+
+    for pattern in patterns {
+      set.add(pattern);
+    }
+
+    set
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::PatternSet;
+  use testing::pattern;
+
+  #[test]
+  fn specificity() {
+    let ranked = ["src/main.rs", "src/*.rs", "src/**", "*.rs", "**"];
+
+    for pair in ranked.windows(2) {
+      assert!(pattern(pair[0]).specificity() > pattern(pair[1]).specificity(), "{:?}", pair);
+    }
+
+    assert!(pattern("a[bc]").specificity() > pattern("a?").specificity());
+    assert!(pattern("a?").specificity() > pattern("a*").specificity());
+    assert!(pattern("[a]").specificity() > pattern("[b]").specificity());
+    assert_eq!(pattern("a/*").specificity(), pattern("a/*").specificity());
+
+    let spec = pattern("/src/**/[a-z]?.rs").specificity();
+    assert_eq!((spec.literals, spec.components), (6, 3));
+    assert_eq!((spec.recursive, spec.sequences, spec.any_chars, spec.classes), (1, 0, 1, 1));
+  }
+
+  #[test]
+  fn most_specific() {
+    let set: PatternSet = ["**", "*.rs", "src/**", "src/*.rs", "src/main.rs", "*.rs"].iter()
+      .map(|p| pattern(*p))
+      .collect();
+
+    assert_eq!(set.len(), 6);
+    assert_eq!(set.matches("src/lib.rs"), vec![0, 1, 2, 3, 5]);
+    assert_eq!(set.most_specific("src/main.rs"), Some(4));
+    assert_eq!(set.most_specific("src/lib.rs"), Some(3));
+    assert_eq!(set.most_specific("src/a/b.rs"), Some(2));
+    assert_eq!(set.most_specific("lib.rs"), Some(1));
+    assert_eq!(set.most_specific("readme"), Some(0));
+    assert_eq!(PatternSet::new().most_specific("readme"), None);
+  }
+}
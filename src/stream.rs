@@ -0,0 +1,202 @@
+//! An asynchronous `Stream` of glob matches, enabled by the `async` feature.
+//!
+//! Directory I/O happens on a dedicated thread, which hands matches over a
+//! bounded channel. It blocks once `buffer` matches are waiting, so a slow
+//! consumer applies backpressure to the walk. Dropping the stream stops the
+//! walk before its next directory listing, even if nothing more matches.
+
+use futures::{Future, Sink, Stream, Poll};
+use futures::sync::mpsc;
+use std::io::{IoError, IoResult};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::Thread;
+
+use entry::{FileKind, GlobEntry, Metadata};
+use filesystem::{FileSystem, DirEntries, OsFs};
+use glob::glob_in;
+use options::GlobOptions;
+use pattern::Error;
+
+pub struct GlobStream {
+  rx: mpsc::Receiver<GlobEntry>,
+  cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for GlobStream {
+  fn drop(&mut self) {
+    self.cancelled.store(true, Ordering::SeqCst);
+  }
+}
+
+// `fs`, until the stream is dropped; listings fail after that, so the walk
+// winds down without finding anything more
+struct Cancellable<F> {
+  fs: F,
+  cancelled: Arc<AtomicBool>,
+}
+
+impl<F: FileSystem> FileSystem for Cancellable<F> {
+  fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+    if self.cancelled.load(Ordering::SeqCst) {
+      return Err(IoError {
+        kind: io::OtherIoError,
+        desc: "glob stream dropped",
+        detail: None,
+      });
+    }
+
+    self.fs.read_dir(path)
+  }
+
+  fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+    self.fs.file_kind(path)
+  }
+
+  fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+    self.fs.metadata(path)
+  }
+
+  fn read_link(&self, path: &Path) -> IoResult<Path> {
+    self.fs.read_link(path)
+  }
+}
+
+impl Stream for GlobStream {
+  type Item = GlobEntry;
+  type Error = ();
+
+  fn poll(&mut self) -> Poll<Option<GlobEntry>, ()> {
+    self.rx.poll()
+  }
+}
+
+/// Like `glob_with`, but yields matches as a `Stream`.
+///
+/// Pattern errors are reported immediately rather than through the stream.
+pub fn glob_stream(pattern: &str, options: &GlobOptions, buffer: usize)
+                   -> Result<GlobStream, Error> {
+  glob_stream_in(OsFs, pattern, options, buffer)
+}
+
+/// Like `glob_stream`, in `fs`, which moves to the walking thread.
+pub fn glob_stream_in<F>(fs: F, pattern: &str, options: &GlobOptions, buffer: usize)
+                         -> Result<GlobStream, Error>
+  where F: FileSystem + Send + 'static {
+  let cancelled = Arc::new(AtomicBool::new(false));
+  let fs = Cancellable { fs: fs, cancelled: cancelled.clone() };
+  let paths = try!(glob_in(fs, pattern, options));
+  let (mut tx, rx) = mpsc::channel(buffer);
+
+  Thread::spawn(move || {
+    for entry in paths {
+      tx = match tx.send(entry).wait() {
+        Ok(tx) => tx,
+        // the stream was dropped
+        Err(..) => return,
+      };
+    }
+  });
+
+  Ok(GlobStream { rx: rx, cancelled: cancelled })
+}
+
+#[cfg(test)]
+mod test {
+  use futures::{Future, Stream};
+  use std::io::{self, IoResult, TempDir};
+  use std::io::fs;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::mpsc::{channel, Sender, Receiver};
+
+  use super::{glob_stream, glob_stream_in};
+  use entry::{FileKind, Metadata};
+  use filesystem::{FileSystem, DirEntries};
+  use memfs::MemoryFs;
+  use options::GlobOptions;
+
+  // an in-memory tree counting its listings, whose first listing waits
+  // for `gate`, and which reports on `done` when the walk lets go of it
+  struct Gated {
+    fs: MemoryFs,
+    reads: Arc<AtomicUsize>,
+    gate: Receiver<()>,
+    done: Sender<()>,
+  }
+
+  impl FileSystem for Gated {
+    fn read_dir(&self, path: &Path) -> IoResult<DirEntries> {
+      if self.reads.fetch_add(1, Ordering::SeqCst) == 0 {
+        self.gate.recv().unwrap();
+      }
+
+      self.fs.read_dir(path)
+    }
+
+    fn file_kind(&self, path: &Path) -> IoResult<FileKind> {
+      self.fs.file_kind(path)
+    }
+
+    fn metadata(&self, path: &Path) -> IoResult<Metadata> {
+      self.fs.metadata(path)
+    }
+
+    fn read_link(&self, path: &Path) -> IoResult<Path> {
+      self.fs.read_link(path)
+    }
+  }
+
+  impl Drop for Gated {
+    fn drop(&mut self) {
+      let _ = self.done.send(());
+    }
+  }
+
+  #[test]
+  fn collects_matches() {
+    let root = TempDir::new("glob-stream").unwrap();
+    fs::mkdir(&root.path().join("a"), io::USER_RWX).unwrap();
+    io::File::create(&root.path().join("a/x.rs")).unwrap();
+    io::File::create(&root.path().join("a/y.rs")).unwrap();
+    io::File::create(&root.path().join("a/z.txt")).unwrap();
+
+    let pattern = root.path().join("**/*.rs(on)");
+    let stream = glob_stream(pattern.as_str().unwrap(), &GlobOptions::new(), 1).unwrap();
+    let paths: Vec<Path> = stream.map(|e| e.into_path()).collect().wait().unwrap();
+
+    assert_eq!(paths, vec![root.path().join("a/x.rs"), root.path().join("a/y.rs")]);
+  }
+
+  #[test]
+  fn drop_mid_walk() {
+    let mut tree = MemoryFs::new();
+
+    for a in range(0, 10) {
+      for b in range(0, 10) {
+        tree.add_dir(format!("{}/{}/", a, b).as_slice());
+      }
+    }
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let (open, gate) = channel();
+    let (done, finished) = channel();
+    let fs = Gated { fs: tree, reads: reads.clone(), gate: gate, done: done };
+
+    // nothing matches, so only the cancellation can end the walk early
+    let stream = glob_stream_in(fs, "**/nothing", &GlobOptions::new(), 1).unwrap();
+    drop(stream);
+    // the walk may not have reached its first listing yet, and so never wait
+    let _ = open.send(());
+    finished.recv().unwrap();
+
+    // of 111 directories, at most the one listed before the drop
+    assert!(reads.load(Ordering::SeqCst) <= 1);
+  }
+
+  #[test]
+  fn pattern_errors() {
+    assert!(glob_stream("a/**b", &GlobOptions::new(), 1).is_err());
+  }
+}
@@ -0,0 +1,515 @@
+//! Globbing the members of tar archives.
+//!
+//! `TarArchive::read` makes one pass over an archive, reading only the
+//! headers and skipping over member data, so it works on pipes and
+//! decompressors as well as files. The members are indexed into a directory
+//! tree, with any directories the archive leaves implicit filled in, and
+//! matched with the same patterns as on disk:
+//!
+//! ```ignore
+//! let archive = try!(TarArchive::read(File::open(&Path::new("dist.tar"))));
+//!
+//! for m in archive.glob("usr/lib/**/*.so", &GlobOptions::new()).unwrap() {
+//!   println!("{} at {}", m.entry.path().display(), m.member.unwrap().offset);
+//! }
+//! ```
+//!
+//! Both ustar and GNU headers are understood, including GNU long names and
+//! the `path` and `linkpath` records of pax extended headers. Pax global
+//! headers, GNU volume labels and multi-volume continuations aren't
+//! members and are skipped. Absolute names are taken from the root. Gzipped
+//! archives can be read with `read_gz` when the `gzip` feature is enabled.
+
+use std::io::{IoError, IoResult};
+use std::io;
+use std::num;
+use std::str;
+
+use archive::{Archive, ArchiveMatch, ArchiveMatches, Member};
+use entry::{FileKind, Metadata};
+
+static BLOCK: usize = 512;
+
+// the most read of a long name or pax extended header, which are read into
+// memory; real ones are far smaller
+static MAX_RECORD: u64 = 4 << 20;
+
+/// A member of the archive, as described by its header.
+#[derive(Clone, Show)]
+pub struct TarMember {
+  pub path: Path,
+  pub kind: FileKind,
+  /// Where the member's data starts, from the start of the archive.
+  pub offset: u64,
+  pub size: u64,
+  /// The target of a symlink or hard link.
+  pub link: Option<Path>,
+}
+
+impl Member for TarMember {
+  fn offset(&self) -> u64 {
+    self.offset
+  }
+
+  fn path_mut(&mut self) -> &mut Path {
+    &mut self.path
+  }
+
+  fn link(&self) -> Option<&Path> {
+    self.link.as_ref()
+  }
+}
+
+pub type TarArchive = Archive<TarMember>;
+pub type TarMatch = ArchiveMatch<TarMember>;
+pub type TarMatches<'a> = ArchiveMatches<'a, TarMember>;
+
+fn invalid(desc: &'static str, detail: Option<String>) -> IoError {
+  IoError {
+    kind: io::InvalidInput,
+    desc: desc,
+    detail: detail,
+  }
+}
+
+// fills `buf` unless the reader runs out first, returning the bytes read
+fn fill<R: Reader>(reader: &mut R, buf: &mut [u8]) -> IoResult<usize> {
+  let mut filled = 0;
+
+  while filled < buf.len() {
+    match reader.read(buf.slice_from_mut(filled)) {
+      Ok(n) => filled += n,
+      Err(ref e) if e.kind == io::EndOfFile => break,
+      Err(e) => return Err(e),
+    }
+  }
+
+  Ok(filled)
+}
+
+fn skip<R: Reader>(reader: &mut R, mut len: u64) -> IoResult<()> {
+  let mut scratch = [0u8; 8192];
+
+  while len > 0 {
+    let chunk = if len < scratch.len() as u64 { len as usize } else { scratch.len() };
+
+    if try!(fill(reader, scratch.slice_to_mut(chunk))) < chunk {
+      return Err(invalid("truncated tar archive", None));
+    }
+
+    len -= chunk as u64;
+  }
+
+  Ok(())
+}
+
+// member data is padded out to whole blocks
+fn padded(size: u64) -> u64 {
+  (size + BLOCK as u64 - 1) / BLOCK as u64 * BLOCK as u64
+}
+
+// a NUL-terminated field
+fn field(bytes: &[u8]) -> &[u8] {
+  match bytes.iter().position(|&b| b == 0) {
+    Some(end) => bytes.slice_to(end),
+    None => bytes,
+  }
+}
+
+// an octal field, or a big-endian binary one when the high bit is set,
+// which GNU tar uses for values that don't fit
+fn number(bytes: &[u8]) -> IoResult<u64> {
+  if bytes.len() > 0 && bytes[0] & 0x80 != 0 {
+    let mut value = (bytes[0] & 0x7f) as u64;
+
+    for &b in bytes.slice_from(1).iter() {
+      if value >> 56 != 0 {
+        return Err(invalid("number in tar header too large", None));
+      }
+
+      value = (value << 8) | b as u64;
+    }
+
+    return Ok(value);
+  }
+
+  let text = str::from_utf8(field(bytes)).ok()
+    .map(|s| s.trim_matches(' '));
+
+  match text {
+    Some("") => Ok(0),
+    Some(text) => num::from_str_radix(text, 8).ok_or_else(|| {
+      invalid("invalid number in tar header", Some(text.to_string()))
+    }),
+    None => Err(invalid("invalid number in tar header", None)),
+  }
+}
+
+// the `path` and `linkpath` records of a pax extended header, each of
+// which is "<length> <key>=<value>\n"
+fn pax_records(mut data: &[u8]) -> IoResult<(Option<Vec<u8>>, Option<Vec<u8>>)> {
+  let mut path = None;
+  let mut link = None;
+
+  while !data.is_empty() {
+    let space = match data.iter().position(|&b| b == b' ') {
+      Some(space) => space,
+      None => break,
+    };
+
+    let len = str::from_utf8(data.slice_to(space)).ok()
+      .and_then(|s| s.parse::<usize>())
+      .unwrap_or(0);
+
+    if len <= space + 1 || len > data.len() {
+      return Err(invalid("invalid pax extended header", None));
+    }
+
+    // without the trailing newline
+    let record = data.slice(space + 1, len - 1);
+
+    if let Some(eq) = record.iter().position(|&b| b == b'=') {
+      // names can't hold a NUL, so one ends the value
+      let value = field(record.slice_from(eq + 1)).to_vec();
+
+      let key = record.slice_to(eq);
+
+      if key == b"path" {
+        path = Some(value);
+      } else if key == b"linkpath" {
+        link = Some(value);
+      }
+    }
+
+    data = data.slice_from(len);
+  }
+
+  Ok((path, link))
+}
+
+impl Archive<TarMember> {
+  /// Indexes an uncompressed tar archive.
+  pub fn read<R: Reader>(mut reader: R) -> IoResult<TarArchive> {
+    let mut archive = Archive::new();
+
+    let mut offset = 0u64;
+    let mut header = [0u8; 512];
+
+    // names that override the next header's, from GNU long name entries
+    // and pax extended headers
+    let mut long_name: Option<Vec<u8>> = None;
+    let mut long_link: Option<Vec<u8>> = None;
+
+    loop {
+      let read = try!(fill(&mut reader, &mut header));
+
+      // a missing end-of-archive marker is common enough to put up with
+      if read == 0 {
+        break;
+      }
+
+      if read < BLOCK {
+        return Err(invalid("truncated tar archive", None));
+      }
+
+      offset += BLOCK as u64;
+
+      if header.iter().all(|&b| b == 0) {
+        break;
+      }
+
+      let checksum = try!(number(header.slice(148, 156)));
+      let actual = header.iter().enumerate()
+        .map(|(i, &b)| if i >= 148 && i < 156 { b' ' as u64 } else { b as u64 })
+        .fold(0, |a, b| a + b);
+
+      if checksum != actual {
+        return Err(invalid("tar header checksum mismatch", Some(format!("at offset {}", offset - BLOCK as u64))));
+      }
+
+      let size = try!(number(header.slice(124, 136)));
+      let typeflag = header[156];
+
+      match typeflag {
+        b'L' | b'K' | b'x' => {
+          if size > MAX_RECORD {
+            return Err(invalid("tar extended header too large", Some(format!("{} bytes", size))));
+          }
+
+          let mut data: Vec<u8> = range(0, size as usize).map(|_| 0).collect();
+
+          if try!(fill(&mut reader, data.as_mut_slice())) < data.len() {
+            return Err(invalid("truncated tar archive", None));
+          }
+
+          try!(skip(&mut reader, padded(size) - size));
+          offset += padded(size);
+
+          match typeflag {
+            b'L' => long_name = Some(field(data.as_slice()).to_vec()),
+            b'K' => long_link = Some(field(data.as_slice()).to_vec()),
+            _ => {
+              let (path, link) = try!(pax_records(data.as_slice()));
+              long_name = path.or(long_name);
+              long_link = link.or(long_link);
+            }
+          }
+
+          continue;
+        }
+        // pax global headers, volume labels and the rest of a file
+        // continued from another volume
+        b'g' | b'V' | b'M' => {
+          try!(skip(&mut reader, padded(size)));
+          offset += padded(size);
+          continue;
+        }
+        _ => {}
+      }
+
+      let name = long_name.take().unwrap_or_else(|| {
+        let name = field(header.slice(0, 100));
+
+        // ustar splits long names, but GNU tar puts other things there
+        if header.slice(257, 263) == b"ustar\0" && header[345] != 0 {
+          let mut joined = field(header.slice(345, 500)).to_vec();
+          joined.push(b'/');
+          joined.push_all(name);
+          joined
+        } else {
+          name.to_vec()
+        }
+      });
+
+      let link = long_link.take()
+        .unwrap_or_else(|| field(header.slice(157, 257)).to_vec());
+
+      // a trailing slash also marks a directory in pre-ustar archives
+      let kind = match typeflag {
+        b'5' => FileKind::Directory,
+        b'2' => FileKind::Symlink,
+        b'3' => FileKind::CharDevice,
+        b'4' => FileKind::BlockDevice,
+        b'6' => FileKind::Fifo,
+        _ if name.last() == Some(&b'/') => FileKind::Directory,
+        b'0' | b'\0' | b'1' | b'7' => FileKind::File,
+        _ => FileKind::Other,
+      };
+
+      let mode = try!(number(header.slice(100, 108))) as u32;
+      let metadata = Metadata {
+        kind: kind,
+        size: size,
+        modified: try!(number(header.slice(136, 148))) * 1000,
+        permissions: mode & 0o7777,
+        uid: try!(number(header.slice(108, 116))),
+        gid: try!(number(header.slice(116, 124))),
+      };
+
+      let link = if typeflag == b'1' || typeflag == b'2' {
+        Some(Path::new(link))
+      } else {
+        None
+      };
+
+      try!(archive.add(name.as_slice(), TarMember {
+        path: Path::new(name.as_slice()),
+        kind: kind,
+        offset: offset,
+        size: size,
+        link: link,
+      }, metadata));
+
+      // hard links and directories have no data, whatever the size says
+      let data = match typeflag {
+        b'1' | b'2' | b'5' => 0,
+        _ => padded(size),
+      };
+
+      try!(skip(&mut reader, data));
+      offset += data;
+    }
+
+    Ok(archive)
+  }
+
+  /// Indexes a gzipped tar archive.
+  #[cfg(feature = "gzip")]
+  pub fn read_gz<R: Reader>(reader: R) -> IoResult<TarArchive> {
+    use flate2::reader::GzDecoder;
+
+    TarArchive::read(try!(GzDecoder::new(reader)))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::MemReader;
+
+  use super::TarArchive;
+  use entry::FileKind;
+  use options::GlobOptions;
+
+  fn header(name: &[u8], typeflag: u8, size: usize, link: &str) -> Vec<u8> {
+    let mut header: Vec<u8> = range(0, 512).map(|_| 0).collect();
+
+    fn put(header: &mut Vec<u8>, at: usize, value: &[u8]) {
+      for (i, &b) in value.iter().enumerate() {
+        header[at + i] = b;
+      }
+    }
+
+    put(&mut header, 0, name);
+    put(&mut header, 100, b"0000644\0");
+    put(&mut header, 108, b"0000000\0");
+    put(&mut header, 116, b"0000000\0");
+    put(&mut header, 124, format!("{:011o}\0", size).as_bytes());
+    put(&mut header, 136, b"00000000000\0");
+    put(&mut header, 148, b"        ");
+    header[156] = typeflag;
+    put(&mut header, 157, link.as_bytes());
+    put(&mut header, 257, b"ustar\000");
+
+    let sum = header.iter().fold(0us, |a, &b| a + b as usize);
+    put(&mut header, 148, format!("{:06o}\0 ", sum).as_bytes());
+    header
+  }
+
+  fn member(archive: &mut Vec<u8>, name: &[u8], typeflag: u8, data: &[u8], link: &str) {
+    archive.push_all(header(name, typeflag, data.len(), link).as_slice());
+    archive.push_all(data);
+
+    while archive.len() % 512 != 0 {
+      archive.push(0);
+    }
+  }
+
+  fn sample() -> Vec<u8> {
+    let long = format!("usr/lib/{}.so", "x".repeat(120));
+
+    let mut archive = Vec::new();
+    member(&mut archive, b"usr/", b'5', b"", "");
+    member(&mut archive, b"usr/lib/libfoo.so", b'0', b"foo", "");
+    member(&mut archive, b"usr/lib/deep/libbar.so", b'0', b"barbar", "");
+    member(&mut archive, b"usr/lib/libfoo.so.1", b'2', b"", "libfoo.so");
+    member(&mut archive, b"././@LongLink", b'L', long.as_bytes(), "");
+    member(&mut archive, b"usr/lib/truncated", b'0', b"long", "");
+    member(&mut archive, b"etc/old-style/", b'0', b"", "");
+    archive.push_all(range(0, 1024).map(|_| 0u8).collect::<Vec<u8>>().as_slice());
+    archive
+  }
+
+  fn glob(archive: &TarArchive, pattern: &str) -> Vec<(String, Option<u64>)> {
+    let mut found: Vec<(String, Option<u64>)> =
+      archive.glob(pattern, &GlobOptions::new()).unwrap()
+        .map(|m| (m.entry.path().display().to_string(), m.member.map(|m| m.offset)))
+        .collect();
+
+    found.sort();
+    found
+  }
+
+  #[test]
+  fn members() {
+    let archive = TarArchive::read(MemReader::new(sample())).unwrap();
+
+    let long = format!("usr/lib/{}.so", "x".repeat(120));
+    assert_eq!(glob(&archive, "usr/lib/**/*.so"), vec![
+      ("usr/lib/deep/libbar.so".to_string(), Some(512 * 4)),
+      ("usr/lib/libfoo.so".to_string(), Some(512 * 2)),
+      (long, Some(512 * 9)),
+    ]);
+
+    let foo = archive.member(&Path::new("/usr/lib/libfoo.so")).unwrap();
+    assert_eq!(foo.size, 3);
+    assert_eq!(foo.kind, FileKind::File);
+
+    let link = archive.member(&Path::new("usr/lib/libfoo.so.1")).unwrap();
+    assert_eq!(link.link, Some(Path::new("libfoo.so")));
+  }
+
+  #[test]
+  fn directories() {
+    let archive = TarArchive::read(MemReader::new(sample())).unwrap();
+
+    // `usr/lib` is only implied, and `etc/old-style` is marked by its slash
+    assert_eq!(glob(&archive, "*/*/"), vec![
+      ("etc/old-style".to_string(), Some(512 * 11)),
+      ("usr/lib".to_string(), None),
+    ]);
+
+    // the symlink resolves to a file
+    assert_eq!(glob(&archive, "usr/lib/*.so.1/"), vec![]);
+  }
+
+  #[test]
+  fn special_headers() {
+    let mut data = Vec::new();
+    member(&mut data, b"pax_global_header", b'g', b"17 comment=hello\n", "");
+    member(&mut data, b"backup", b'V', b"", "");
+    member(&mut data, b"/etc/passwd", b'0', b"root", "");
+    member(&mut data, b"caf\xe9", b'0', b"", "");
+    member(&mut data, b"var/log/big", b'M', b"rest", "");
+    data.push_all(range(0, 1024).map(|_| 0u8).collect::<Vec<u8>>().as_slice());
+
+    let archive = TarArchive::read(MemReader::new(data)).unwrap();
+    assert_eq!(archive.members().len(), 2);
+    assert_eq!(glob(&archive, "*").len(), 2);
+
+    // absolute names are found either way
+    assert_eq!(archive.member(&Path::new("/etc/passwd")).unwrap().size, 4);
+    assert_eq!(archive.member(&Path::new("etc/passwd")).unwrap().path, Path::new("etc/passwd"));
+
+    // and names that aren't UTF-8 are found through the glob
+    let found: Vec<_> = archive.glob("caf?", &GlobOptions::new()).unwrap().collect();
+    assert_eq!(found.len(), 1);
+    assert!(found[0].member.is_some());
+  }
+
+  #[test]
+  fn members_under_files() {
+    let mut data = Vec::new();
+    member(&mut data, b"a", b'0', b"", "");
+    member(&mut data, b"a/b", b'0', b"", "");
+    data.push_all(range(0, 1024).map(|_| 0u8).collect::<Vec<u8>>().as_slice());
+    assert!(TarArchive::read(MemReader::new(data)).is_err());
+
+    let mut data = Vec::new();
+    member(&mut data, b"dir/", b'5', b"", "");
+    member(&mut data, b"link", b'2', b"", "dir");
+    member(&mut data, b"link/escaped", b'0', b"", "");
+    data.push_all(range(0, 1024).map(|_| 0u8).collect::<Vec<u8>>().as_slice());
+    assert!(TarArchive::read(MemReader::new(data)).is_err());
+
+    // the root itself is no member, but no trouble either
+    let mut data = Vec::new();
+    member(&mut data, b"./", b'5', b"", "");
+    member(&mut data, b"./a", b'0', b"", "");
+    data.push_all(range(0, 1024).map(|_| 0u8).collect::<Vec<u8>>().as_slice());
+    assert_eq!(TarArchive::read(MemReader::new(data)).unwrap().members().len(), 1);
+  }
+
+  #[test]
+  fn oversized() {
+    // a long name of 8 MiB
+    let long: Vec<u8> = range(0, 8 << 20).map(|_| b'a').collect();
+    let mut data = Vec::new();
+    member(&mut data, b"././@LongLink", b'L', long.as_slice(), "");
+    member(&mut data, b"short", b'0', b"", "");
+    assert!(TarArchive::read(MemReader::new(data)).is_err());
+
+    assert_eq!(super::number(b"\x80\0\0\0\0\0\0\0\0\0\0\x01").unwrap(), 1);
+    assert!(super::number(b"\x80\0\0\x01\0\0\0\0\0\0\0\0").is_err());
+    assert!(super::number(b"\x81\0\0\0\0\0\0\0\0\0\0\0").is_err());
+  }
+
+  #[test]
+  fn corrupt() {
+    let mut archive = sample();
+    archive[0] = b'v';
+    assert!(TarArchive::read(MemReader::new(archive)).is_err());
+
+    let archive = sample();
+    assert!(TarArchive::read(MemReader::new(archive.slice_to(600).to_vec())).is_err());
+  }
+}
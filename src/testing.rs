@@ -0,0 +1,26 @@
+//! Fixtures shared by the unit tests.
+
+use std::io::{self, TempDir};
+use std::io::fs;
+
+use pattern::{Pattern, PathStyle};
+
+/// A temporary directory holding `dirs`, then `files`, both relative to it.
+pub fn tree(prefix: &str, dirs: &[&str], files: &[&str]) -> TempDir {
+  let root = TempDir::new(prefix).unwrap();
+
+  for dir in dirs.iter() {
+    fs::mkdir(&root.path().join(*dir), io::USER_RWX).unwrap();
+  }
+
+  for file in files.iter() {
+    io::File::create(&root.path().join(*file)).unwrap();
+  }
+
+  root
+}
+
+/// A pattern in the POSIX style, whatever the platform.
+pub fn pattern(pattern: &str) -> Pattern {
+  Pattern::with_style(pattern, PathStyle::Posix).unwrap()
+}
@@ -0,0 +1,356 @@
+//! Globbing the members of zip archives.
+//!
+//! Only the central directory at the end of the archive is read, so
+//! finding `META-INF/**/*.xml` in a large jar costs a couple of seeks.
+//! Member names are indexed into a directory tree, including directories
+//! that only appear as the prefix of other names, and matched with the same
+//! patterns as on disk. Zip64 archives are supported.
+//!
+//! Symlinks stored by Unix zip tools keep their target in the member data,
+//! which isn't read here, so they're listed as plain files.
+
+use std::io::{IoError, IoResult, Seek, SeekSet, SeekEnd};
+use std::io;
+
+use archive::{Archive, ArchiveMatch, ArchiveMatches, Member};
+use entry::{FileKind, Metadata};
+
+static END_OF_DIRECTORY: u32 = 0x06054b50;
+static ZIP64_END_OF_DIRECTORY: u32 = 0x06064b50;
+static ZIP64_LOCATOR: u32 = 0x07064b50;
+static DIRECTORY_ENTRY: u32 = 0x02014b50;
+
+// the end of central directory record, without its trailing comment
+static END_LEN: usize = 22;
+static ZIP64_LOCATOR_LEN: usize = 20;
+static ENTRY_LEN: usize = 46;
+
+/// A member of the archive, as described by its central directory entry.
+#[derive(Clone, Show)]
+pub struct ZipMember {
+  pub path: Path,
+  pub kind: FileKind,
+  /// The compression method, e.g. 0 for stored or 8 for deflated.
+  pub method: u16,
+  pub compressed_size: u64,
+  pub size: u64,
+  pub crc32: u32,
+  /// Where the member's local header starts, from the start of the archive.
+  pub header_offset: u64,
+}
+
+impl Member for ZipMember {
+  fn offset(&self) -> u64 {
+    self.header_offset
+  }
+
+  fn path_mut(&mut self) -> &mut Path {
+    &mut self.path
+  }
+}
+
+pub type ZipArchive = Archive<ZipMember>;
+pub type ZipMatch = ArchiveMatch<ZipMember>;
+pub type ZipMatches<'a> = ArchiveMatches<'a, ZipMember>;
+
+fn invalid(desc: &'static str) -> IoError {
+  IoError {
+    kind: io::InvalidInput,
+    desc: desc,
+    detail: None,
+  }
+}
+
+fn le16(buf: &[u8], at: usize) -> u16 {
+  buf[at] as u16 | (buf[at + 1] as u16) << 8
+}
+
+fn le32(buf: &[u8], at: usize) -> u32 {
+  le16(buf, at) as u32 | (le16(buf, at + 2) as u32) << 16
+}
+
+fn le64(buf: &[u8], at: usize) -> u64 {
+  le32(buf, at) as u64 | (le32(buf, at + 4) as u64) << 32
+}
+
+// milliseconds since the epoch of an MS-DOS date and time, taken as UTC
+fn dos_time(date: u16, time: u16) -> u64 {
+  let year = 1980 + (date >> 9) as i64;
+  let month = ((date >> 5) & 0xf) as i64;
+  let day = (date & 0x1f) as i64;
+
+  // days from the epoch to a civil date
+  let y = if month <= 2 { year - 1 } else { year };
+  let era = y / 400;
+  let yoe = y - era * 400;
+  let mp = (month + 9) % 12;
+  let doy = (153 * mp + 2) / 5 + day - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  let days = era * 146097 + doe - 719468;
+
+  let seconds = (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3f) as i64 * 60 + (time & 0x1f) as i64 * 2;
+  ((days * 86400 + seconds) * 1000) as u64
+}
+
+// finds the central directory, returning its offset, size and entry count
+fn locate<R: Reader + Seek>(reader: &mut R) -> IoResult<(u64, u64, u64)> {
+  try!(reader.seek(0, SeekEnd));
+  let len = try!(reader.tell());
+
+  if len < END_LEN as u64 {
+    return Err(invalid("not a zip archive"));
+  }
+
+  // the record is followed by a comment of up to 64k
+  let tail_len = if len < (END_LEN + 0xffff) as u64 { len } else { (END_LEN + 0xffff) as u64 };
+  let tail_start = len - tail_len;
+  try!(reader.seek(tail_start as i64, SeekSet));
+  let tail = try!(reader.read_exact(tail_len as usize));
+
+  let end = try!(range(0, tail.len() - END_LEN + 1).rev()
+    .find(|&i| le32(tail.as_slice(), i) == END_OF_DIRECTORY)
+    .ok_or(invalid("not a zip archive")));
+
+  let record = tail.slice_from(end);
+  let count = le16(record, 10) as u64;
+  let size = le32(record, 12) as u64;
+  let offset = le32(record, 16) as u64;
+
+  if count != 0xffff && size != 0xffffffff && offset != 0xffffffff {
+    return Ok((offset, size, count));
+  }
+
+  // a zip64 archive, whose real end record is found through a locator
+  // just before the regular one
+  if end < ZIP64_LOCATOR_LEN || le32(tail.as_slice(), end - ZIP64_LOCATOR_LEN) != ZIP64_LOCATOR {
+    return Ok((offset, size, count));
+  }
+
+  let zip64_end = le64(tail.as_slice(), end - ZIP64_LOCATOR_LEN + 8);
+  try!(reader.seek(zip64_end as i64, SeekSet));
+  let record = try!(reader.read_exact(56));
+
+  if le32(record.as_slice(), 0) != ZIP64_END_OF_DIRECTORY {
+    return Err(invalid("invalid zip64 end of central directory"));
+  }
+
+  Ok((le64(record.as_slice(), 48), le64(record.as_slice(), 40), le64(record.as_slice(), 32)))
+}
+
+impl Archive<ZipMember> {
+  /// Indexes a zip archive from its central directory.
+  pub fn read<R: Reader + Seek>(mut reader: R) -> IoResult<ZipArchive> {
+    let (offset, size, count) = try!(locate(&mut reader));
+
+    try!(reader.seek(offset as i64, SeekSet));
+    let directory = try!(reader.read_exact(size as usize));
+    let directory = directory.as_slice();
+
+    let mut archive = Archive::new();
+
+    let mut at = 0;
+
+    for _ in range(0, count) {
+      if at + ENTRY_LEN > directory.len() || le32(directory, at) != DIRECTORY_ENTRY {
+        return Err(invalid("invalid zip central directory"));
+      }
+
+      let entry = directory.slice_from(at);
+      let name_len = le16(entry, 28) as usize;
+      let extra_len = le16(entry, 30) as usize;
+      let comment_len = le16(entry, 32) as usize;
+
+      if ENTRY_LEN + name_len + extra_len + comment_len > entry.len() {
+        return Err(invalid("invalid zip central directory"));
+      }
+
+      let name = entry.slice(ENTRY_LEN, ENTRY_LEN + name_len);
+      let extra = entry.slice(ENTRY_LEN + name_len, ENTRY_LEN + name_len + extra_len);
+
+      let mut compressed_size = le32(entry, 20) as u64;
+      let mut size = le32(entry, 24) as u64;
+      let mut header_offset = le32(entry, 42) as u64;
+
+      // zip64 sizes and offsets, present only for the fields that overflowed
+      let mut field = 0;
+      while field + 4 <= extra.len() {
+        let id = le16(extra, field);
+        let len = le16(extra, field + 2) as usize;
+        let data = extra.slice(field + 4, ::std::cmp::min(field + 4 + len, extra.len()));
+
+        if id == 0x0001 {
+          let mut next = 0;
+
+          for value in [&mut size, &mut compressed_size, &mut header_offset].iter_mut() {
+            if **value == 0xffffffff && next + 8 <= data.len() {
+              **value = le64(data, next);
+              next += 8;
+            }
+          }
+        }
+
+        field += 4 + len;
+      }
+
+      // Unix tools keep the mode in the high half of the external attributes
+      let mode = if le16(entry, 4) >> 8 == 3 { le32(entry, 38) >> 16 } else { 0 };
+
+      let kind = if name.last() == Some(&b'/') || mode & 0o170000 == 0o040000 {
+        FileKind::Directory
+      } else {
+        FileKind::File
+      };
+
+      let permissions = match (mode & 0o7777, kind) {
+        (0, FileKind::Directory) => 0o755,
+        (0, _) => 0o644,
+        (bits, _) => bits,
+      };
+
+      let metadata = Metadata {
+        kind: kind,
+        size: size,
+        modified: dos_time(le16(entry, 14), le16(entry, 12)),
+        permissions: permissions,
+        uid: 0,
+        gid: 0,
+      };
+
+      if name.contains(&0) {
+        return Err(invalid("NUL in zip member name"));
+      }
+
+      try!(archive.add(name, ZipMember {
+        path: Path::new(name),
+        kind: kind,
+        method: le16(entry, 10),
+        compressed_size: compressed_size,
+        size: size,
+        crc32: le32(entry, 16),
+        header_offset: header_offset,
+      }, metadata));
+
+      at += ENTRY_LEN + name_len + extra_len + comment_len;
+    }
+
+    Ok(archive)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::io::MemReader;
+
+  use super::{ZipArchive, dos_time};
+  use entry::FileKind;
+  use filesystem::FileSystem;
+  use options::GlobOptions;
+
+  fn push16(buf: &mut Vec<u8>, value: u16) {
+    buf.push(value as u8);
+    buf.push((value >> 8) as u8);
+  }
+
+  fn push32(buf: &mut Vec<u8>, value: u32) {
+    push16(buf, value as u16);
+    push16(buf, (value >> 16) as u16);
+  }
+
+  // an archive of empty, stored members
+  fn archive(names: &[&str]) -> Vec<u8> {
+    let mut zip = Vec::new();
+    let mut offsets = Vec::new();
+
+    for name in names.iter() {
+      offsets.push(zip.len() as u32);
+      push32(&mut zip, 0x04034b50);
+      zip.push_all(&[0u8; 22]);
+      push16(&mut zip, name.len() as u16);
+      push16(&mut zip, 0);
+      zip.push_all(name.as_bytes());
+    }
+
+    let start = zip.len();
+
+    for (name, &offset) in names.iter().zip(offsets.iter()) {
+      push32(&mut zip, 0x02014b50);
+      push16(&mut zip, 20);
+      push16(&mut zip, 20);
+      push16(&mut zip, 0);
+      push16(&mut zip, 0);
+      push16(&mut zip, 0);
+      push16(&mut zip, (35 << 9) | (1 << 5) | 1);
+      zip.push_all(&[0u8; 12]);
+      push16(&mut zip, name.len() as u16);
+      zip.push_all(&[0u8; 12]);
+      push32(&mut zip, offset);
+      zip.push_all(name.as_bytes());
+    }
+
+    let size = zip.len() - start;
+
+    push32(&mut zip, 0x06054b50);
+    push32(&mut zip, 0);
+    push16(&mut zip, names.len() as u16);
+    push16(&mut zip, names.len() as u16);
+    push32(&mut zip, size as u32);
+    push32(&mut zip, start as u32);
+    push16(&mut zip, 7);
+    zip.push_all(b"comment");
+    zip
+  }
+
+  fn glob(archive: &ZipArchive, pattern: &str) -> Vec<(String, bool)> {
+    let mut found: Vec<(String, bool)> =
+      archive.glob(pattern, &GlobOptions::new()).unwrap()
+        .map(|m| (m.entry.path().display().to_string(), m.member.is_some()))
+        .collect();
+
+    found.sort();
+    found
+  }
+
+  #[test]
+  fn implicit_directories() {
+    let zip = archive(&["META-INF/", "META-INF/MANIFEST.MF", "META-INF/maven/a/b/pom.xml",
+                        "META-INF/plexus/components.xml", "com/example/Main.class"]);
+    let archive = ZipArchive::read(MemReader::new(zip)).unwrap();
+
+    assert_eq!(glob(&archive, "META-INF/**/*.xml"), vec![
+      ("META-INF/maven/a/b/pom.xml".to_string(), true),
+      ("META-INF/plexus/components.xml".to_string(), true),
+    ]);
+
+    assert_eq!(glob(&archive, "*/"), vec![
+      ("META-INF".to_string(), true),
+      ("com".to_string(), false),
+    ]);
+
+    let class = archive.member(&Path::new("com/example/Main.class")).unwrap();
+    assert_eq!(class.kind, FileKind::File);
+    assert!(class.header_offset > 0);
+
+    // 2015-01-01, at midnight
+    let modified = archive.metadata(&Path::new("com/example/Main.class")).unwrap().modified;
+    assert_eq!(modified, 1420070400000);
+  }
+
+  #[test]
+  fn dos_times() {
+    assert_eq!(dos_time((0 << 9) | (1 << 5) | 1, 0), 315532800000);
+    assert_eq!(dos_time((44 << 9) | (2 << 5) | 29, (12 << 11) | (30 << 5) | 5), 1709209810000);
+  }
+
+  #[test]
+  fn members_under_files() {
+    assert!(ZipArchive::read(MemReader::new(archive(&["a", "a/b"]))).is_err());
+    assert!(ZipArchive::read(MemReader::new(archive(&["a/b", "a"]))).is_ok());
+    assert!(ZipArchive::read(MemReader::new(archive(&["a\0b"]))).is_err());
+  }
+
+  #[test]
+  fn not_a_zip() {
+    assert!(ZipArchive::read(MemReader::new(range(0, 100).map(|_| 0u8).collect())).is_err());
+    assert!(ZipArchive::read(MemReader::new(vec![])).is_err());
+  }
+}
@@ -0,0 +1,275 @@
+// The glob-std expectations, run against an in-memory tree instead, so they
+// hold on every platform without touching the disk.
+
+extern crate glob_prime;
+
+use glob_prime::glob::glob_in;
+use glob_prime::memfs::MemoryFs;
+use glob_prime::options::GlobOptions;
+
+use std::collections::HashSet;
+
+macro_rules! assert_eq {
+  ($e1:expr, $e2:expr) => (
+    if $e1 != $e2 {
+      panic!("{} != {}", stringify!($e1), stringify!($e2))
+    }
+  )
+}
+
+macro_rules! set {
+  ($($e:expr),*) => ({
+    let mut _temp = ::std::collections::HashSet::new();
+    $(_temp.insert($e);)*
+      _temp
+  })
+}
+
+#[test]
+fn main() {
+  let fs = MemoryFs::from_tree("
+    aaa/
+      apple/
+      orange/
+      tomato/
+        tomato.txt
+        tomoto.txt
+    bbb/
+      specials/
+        !
+        *
+        ?
+        [
+        ]
+    ccc/
+    xyz/
+      x
+      y
+      z
+    r/
+      current_dir.md
+      one/
+        a.md
+        another/
+          a.md
+      another/
+        a.md
+      two/
+        b.md
+      three/
+        c.md
+  ");
+
+  let glob_set_with = |&: pattern: &str, options: &GlobOptions| -> HashSet<Path> {
+    glob_in(&fs, pattern, options).unwrap().map(|e| e.into_path()).collect()
+  };
+
+  let glob_set = |&: pattern: &str| glob_set_with(pattern, &GlobOptions::new());
+
+  // all recursive entities
+  assert_eq!(glob_set("r/**"), set!(
+    Path::new("r"),
+    Path::new("r/one"),
+    Path::new("r/one/another"),
+    Path::new("r/another"),
+    Path::new("r/two"),
+    Path::new("r/three")));
+
+  // collapse consecutive recursive patterns
+  assert_eq!(glob_set("r/**/**"), set!(
+    Path::new("r"),
+    Path::new("r/one"),
+    Path::new("r/one/another"),
+    Path::new("r/another"),
+    Path::new("r/two"),
+    Path::new("r/three")));
+
+  // followed by a wildcard
+  assert_eq!(glob_set("r/**/*.md"), set!(
+    Path::new("r/another/a.md"),
+    Path::new("r/current_dir.md"),
+    Path::new("r/one/a.md"),
+    Path::new("r/one/another/a.md"),
+    Path::new("r/three/c.md"),
+    Path::new("r/two/b.md")));
+
+  // followed by a precise pattern
+  assert_eq!(glob_set("r/one/**/a.md"), set!(
+    Path::new("r/one/a.md"),
+    Path::new("r/one/another/a.md")));
+
+  // followed by another recursive pattern
+  // collapses consecutive recursives into one
+  assert_eq!(glob_set("r/one/**/**/a.md"), set!(
+    Path::new("r/one/a.md"),
+    Path::new("r/one/another/a.md")));
+
+  // followed by two precise patterns
+  assert_eq!(glob_set("r/**/another/a.md"), set!(
+    Path::new("r/another/a.md"),
+    Path::new("r/one/another/a.md")));
+
+  // file type filters
+  assert_eq!(glob_set_with("r/**/*", &GlobOptions::files_only()), set!(
+    Path::new("r/another/a.md"),
+    Path::new("r/current_dir.md"),
+    Path::new("r/one/a.md"),
+    Path::new("r/one/another/a.md"),
+    Path::new("r/three/c.md"),
+    Path::new("r/two/b.md")));
+
+  assert_eq!(glob_set_with("r/*", &GlobOptions::dirs_only()), set!(
+    Path::new("r/one"),
+    Path::new("r/another"),
+    Path::new("r/two"),
+    Path::new("r/three")));
+
+  assert_eq!(glob_set_with("r/**", &GlobOptions::files_only()), set!());
+
+  // zsh qualifiers
+  assert_eq!(glob_set("r/**/*(.)"), glob_set_with("r/**/*", &GlobOptions::files_only()));
+  assert_eq!(glob_set("r/*(/)"), glob_set_with("r/*", &GlobOptions::dirs_only()));
+  assert_eq!(glob_set("r/*(/on[1,2])"), set!(
+    Path::new("r/another"),
+    Path::new("r/one")));
+  assert_eq!(glob_set("r/*(/On[1])"), set!(Path::new("r/two")));
+  assert_eq!(glob_set_with("r/current_dir.md/", &GlobOptions::files_only()), set!());
+
+  // TODO: fix
+  // assert_eq!(glob_set(""), set!());
+  // TODO: this seems weird
+  assert_eq!(glob_set("."), set!(Path::new(".")));
+  assert_eq!(glob_set(".."), set!(Path::new("..")));
+
+  assert_eq!(glob_set("aaa"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("aaa/"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("a"), set!());
+  assert_eq!(glob_set("aa"), set!());
+  assert_eq!(glob_set("aaaa"), set!());
+
+  assert_eq!(glob_set("aaa/apple"), set!(Path::new("aaa/apple")));
+  assert_eq!(glob_set("aaa/apple/nope"), set!());
+
+  assert_eq!(glob_set("???/"), set!(
+    Path::new("aaa"),
+    Path::new("bbb"),
+    Path::new("ccc"),
+    Path::new("xyz")));
+
+  assert_eq!(glob_set("aaa/tomato/tom?to.txt"), set!(
+    Path::new("aaa/tomato/tomato.txt"),
+    Path::new("aaa/tomato/tomoto.txt")));
+
+  assert_eq!(glob_set("xyz/?"), set!(
+    Path::new("xyz/x"),
+    Path::new("xyz/y"),
+    Path::new("xyz/z")));
+
+  assert_eq!(glob_set("a*"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("*a*"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("a*a"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("aaa*"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("*aaa"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("*aaa*"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("*a*a*a*"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("aaa*/"), set!(Path::new("aaa")));
+
+  assert_eq!(glob_set("aaa/*"), set!(
+    Path::new("aaa/apple"),
+    Path::new("aaa/orange"),
+    Path::new("aaa/tomato")));
+
+  assert_eq!(glob_set("aaa/*a*"), set!(
+    Path::new("aaa/apple"),
+    Path::new("aaa/orange"),
+    Path::new("aaa/tomato")));
+
+  assert_eq!(glob_set("*/*/*.txt"), set!(
+    Path::new("aaa/tomato/tomato.txt"),
+    Path::new("aaa/tomato/tomoto.txt")));
+
+  assert_eq!(glob_set("*/*/t[aob]m?to[.]t[!y]t"), set!(
+    Path::new("aaa/tomato/tomato.txt"),
+    Path::new("aaa/tomato/tomoto.txt")));
+
+  assert_eq!(glob_set("./aaa"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("./*"), glob_set("*"));
+  // TODO: what
+  // assert_eq!(glob_set("*/..").pop().unwrap(), Path::new("."));
+  assert_eq!(glob_set("aaa/../bbb"), set!(Path::new("bbb")));
+  assert_eq!(glob_set("nonexistent/../bbb"), set!());
+  assert_eq!(glob_set("aaa/tomato/tomato.txt/.."), set!());
+
+  assert_eq!(glob_set("aaa/tomato/tomato.txt/"), set!());
+
+  assert_eq!(glob_set("aa[a]"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("aa[abc]"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("a[bca]a"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("aa[b]"), set!());
+  assert_eq!(glob_set("aa[xyz]"), set!());
+  assert_eq!(glob_set("aa[]]"), set!());
+
+  assert_eq!(glob_set("aa[!b]"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("aa[!bcd]"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("a[!bcd]a"), set!(Path::new("aaa")));
+  assert_eq!(glob_set("aa[!a]"), set!());
+  assert_eq!(glob_set("aa[!abc]"), set!());
+
+  assert_eq!(glob_set("bbb/specials/[[]"), set!(Path::new("bbb/specials/[")));
+  assert_eq!(glob_set("bbb/specials/!"), set!(Path::new("bbb/specials/!")));
+  assert_eq!(glob_set("bbb/specials/[]]"), set!(Path::new("bbb/specials/]")));
+
+  assert_eq!(glob_set("bbb/specials/[*]"), set!(Path::new("bbb/specials/*")));
+  assert_eq!(glob_set("bbb/specials/[?]"), set!(Path::new("bbb/specials/?")));
+
+  assert_eq!(glob_set("bbb/specials/[![]"), set!(
+    Path::new("bbb/specials/!"),
+    Path::new("bbb/specials/*"),
+    Path::new("bbb/specials/?"),
+    Path::new("bbb/specials/]")));
+
+  assert_eq!(glob_set("bbb/specials/[!]]"), set!(
+    Path::new("bbb/specials/!"),
+    Path::new("bbb/specials/*"),
+    Path::new("bbb/specials/?"),
+    Path::new("bbb/specials/[")));
+
+  assert_eq!(glob_set("bbb/specials/[!!]"), set!(
+    Path::new("bbb/specials/*"),
+    Path::new("bbb/specials/?"),
+    Path::new("bbb/specials/["),
+    Path::new("bbb/specials/]")));
+
+  assert_eq!(glob_set("bbb/specials/[!*]"), set!(
+    Path::new("bbb/specials/!"),
+    Path::new("bbb/specials/?"),
+    Path::new("bbb/specials/["),
+    Path::new("bbb/specials/]")));
+
+  assert_eq!(glob_set("bbb/specials/[!?]"), set!(
+    Path::new("bbb/specials/!"),
+    Path::new("bbb/specials/*"),
+    Path::new("bbb/specials/["),
+    Path::new("bbb/specials/]")));
+}
+
+#[test]
+fn literal_parentheses() {
+  let fs = MemoryFs::from_tree("
+    backup(1)
+    docs/
+      foo(bar)
+      foo.md
+  ");
+
+  let glob_set = |&: pattern: &str| -> HashSet<Path> {
+    glob_in(&fs, pattern, &GlobOptions::new()).unwrap().map(|e| e.into_path()).collect()
+  };
+
+  // a trailing group is always read as qualifiers, unless its `)` is
+  // escaped
+  assert!(glob_in(&fs, "backup(1)", &GlobOptions::new()).is_err());
+  assert_eq!(glob_set("backup(1[)]"), set!(Path::new("backup(1)")));
+  assert_eq!(glob_set("docs/*(bar[)]"), set!(Path::new("docs/foo(bar)")));
+  assert_eq!(glob_set("docs/*(.)"), set!(Path::new("docs/foo(bar)"), Path::new("docs/foo.md")));
+}
@@ -12,7 +12,8 @@
 
 extern crate glob_prime;
 
-use glob_prime::glob::glob;
+use glob_prime::glob::{glob, glob_with};
+use glob_prime::options::GlobOptions;
 use std::os;
 use std::io;
 use std::io::TempDir;
@@ -46,7 +47,11 @@ fn main() {
   }
 
   fn glob_set(pattern: &str) -> HashSet<Path> {
-    glob(pattern).unwrap().collect()
+    glob(pattern).unwrap().map(|e| e.into_path()).collect()
+  }
+
+  fn glob_set_with(pattern: &str, options: &GlobOptions) -> HashSet<Path> {
+    glob_with(pattern, options).unwrap().map(|e| e.into_path()).collect()
   }
 
   let root = TempDir::new("glob-tests");
@@ -133,6 +138,33 @@ fn main() {
     Path::new("r/another/a.md"),
     Path::new("r/one/another/a.md")));
 
+  // file type filters
+  assert_eq!(glob_set_with("r/**/*", &GlobOptions::files_only()), set!(
+    Path::new("r/another/a.md"),
+    Path::new("r/current_dir.md"),
+    Path::new("r/one/a.md"),
+    Path::new("r/one/another/a.md"),
+    Path::new("r/three/c.md"),
+    Path::new("r/two/b.md")));
+
+  assert_eq!(glob_set_with("r/*", &GlobOptions::dirs_only()), set!(
+    Path::new("r/one"),
+    Path::new("r/another"),
+    Path::new("r/two"),
+    Path::new("r/three")));
+
+  assert_eq!(glob_set_with("r/**", &GlobOptions::files_only()), set!());
+
+  // zsh qualifiers
+  assert_eq!(glob_set("r/**/*(.)"), glob_set_with("r/**/*", &GlobOptions::files_only()));
+  assert_eq!(glob_set("r/*(/)"), glob_set_with("r/*", &GlobOptions::dirs_only()));
+  assert_eq!(glob_set("r/*(/on[1,2])"), set!(
+    Path::new("r/another"),
+    Path::new("r/one")));
+  assert_eq!(glob_set("r/*(/On[1])"), set!(Path::new("r/two")));
+  assert!(glob("r/*(q)").is_err());
+  assert_eq!(glob_set_with("r/current_dir.md/", &GlobOptions::files_only()), set!());
+
   // TODO: fix
   // assert_eq!(glob_set(""), set!());
   // TODO: this seems weird